@@ -1,35 +1,111 @@
 use std::{
-    fs,
+    collections::HashSet,
+    fs::{self, File},
+    io::Read,
     path::{Path, PathBuf},
+    slice,
+    sync::{
+        mpsc::{self, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
 use color_eyre::{eyre::Error, Result};
+use serde_json::json;
 
-use crate::{command::BacklightCommand, notify::notify};
+use crate::{
+    command::{power::is_plugged, BacklightCommand},
+    config::{BacklightConfig, IdleConfig},
+    daemon::Daemon,
+    format::Format,
+    levels::Levels,
+    notify::notify,
+    sysfs::{Backend, Device, Fade},
+};
 
 pub struct BacklightSpec {
     silent: bool,
+    format: Format,
+    device: Option<String>,
+    fade: Duration,
+    idle: IdleConfig,
 }
 
+const SUBSYSTEM: &str = "leds";
+const IDLE_POLL: Duration = Duration::from_secs(1);
+// Input event codes: https://www.kernel.org/doc/html/latest/input/event-codes.html
+const KEY_A: usize = 30;
+const BTN_LEFT: usize = 0x110;
+const BTN_TOUCH: usize = 0x14a;
+
 impl BacklightSpec {
-    pub fn new(silent: bool) -> Self {
-        BacklightSpec { silent }
+    pub fn new(
+        silent: bool,
+        format: Format,
+        device: Option<String>,
+        fade: u64,
+        config: BacklightConfig,
+    ) -> Self {
+        BacklightSpec {
+            silent,
+            format,
+            device,
+            fade: Duration::from_millis(fade),
+            idle: config.idle,
+        }
     }
 
     pub fn run(&self, modifier: BacklightCommand) -> Result<()> {
+        if let BacklightCommand::List = modifier {
+            return self.list();
+        }
+
+        let device = self.get_device()?;
+
+        match modifier {
+            BacklightCommand::Daemon { timeout } => {
+                let timeout = Duration::from_secs(timeout.unwrap_or(self.idle.timeout));
+                return self.daemon(&device, timeout);
+            }
+            BacklightCommand::Trigger { trigger } => return self.trigger(&device, trigger),
+            _ => {}
+        }
+
+        let changed = matches!(
+            modifier,
+            BacklightCommand::Add { .. }
+                | BacklightCommand::Sub { .. }
+                | BacklightCommand::Set { .. }
+        );
+
         let result = match modifier {
             BacklightCommand::Add { n } => {
-                self.set_brightness(self.get_brightness()? as f32 + n as f32)
+                self.change_brightness(&device, |percent| percent + n as f32)
             }
             BacklightCommand::Sub { n } => {
-                self.set_brightness(self.get_brightness()? as f32 - n as f32)
+                self.change_brightness(&device, |percent| percent - n as f32)
             }
-            BacklightCommand::Set { n } => self.set_brightness((n as f32).min(100.0).max(0.0)),
-            BacklightCommand::Get => Ok(()),
+            BacklightCommand::Set { n } => self.change_brightness(&device, |_| n as f32),
+            BacklightCommand::Save => return self.save(&device),
+            BacklightCommand::Watch => return self.watch(&device),
+            BacklightCommand::Restore => {
+                self.restore(&device)?;
+                return self.show_brightness(&device);
+            }
+            BacklightCommand::Get
+            | BacklightCommand::List
+            | BacklightCommand::Trigger { .. }
+            | BacklightCommand::Daemon { .. } => Ok(()),
         };
 
+        if changed && result.is_ok() {
+            Levels::remember_current(slice::from_ref(&device));
+        }
+
         match result {
-            Ok(_) => self.show_brightness()?,
+            Ok(_) => self.show_brightness(&device)?,
             Err(ref e) => {
                 notify(
                     false,
@@ -37,8 +113,8 @@ impl BacklightSpec {
                     "Backlight brightness cannot be modified. See documentation for more information",
                 )?;
                 eprintln!(
-                    r#"Backlight file is not writable without giving the current user access. See
-                    https://github.com/nate-craft/dashi for more information"#
+                    r#"Backlight could not be set through systemd-logind and the backlight file is not
+                    writable by the current user. See https://github.com/nate-craft/dashi for more information"#
                 );
                 eprintln!("Error: {}", e);
             }
@@ -47,8 +123,208 @@ impl BacklightSpec {
         result
     }
 
-    fn show_brightness(&self) -> Result<()> {
-        let brightness_new = self.get_brightness()?;
+    fn list(&self) -> Result<()> {
+        let mut plain = Vec::new();
+        let mut devices = Vec::new();
+
+        for device in Device::list(SUBSYSTEM)? {
+            let brightness = device.brightness()?;
+            let max_brightness = device.max_brightness()?;
+            let trigger = Self::get_trigger(&device).unwrap_or_else(|_| "unknown".to_string());
+
+            plain.push(format!(
+                "{}: {}/{} ({})",
+                device.name(),
+                brightness,
+                max_brightness,
+                trigger
+            ));
+            devices.push(json!({
+                "device": device.name(),
+                "brightness": brightness,
+                "max_brightness": max_brightness,
+                "trigger": trigger,
+            }));
+        }
+
+        self.format.print(plain.join("\n"), json!(devices));
+        Ok(())
+    }
+
+    fn trigger(&self, device: &Device, trigger: Option<String>) -> Result<()> {
+        match trigger {
+            Some(trigger) => {
+                device.write("trigger", &trigger)?;
+                notify(self.silent, "Backlight Trigger", &trigger)?;
+            }
+            None => {
+                let trigger = Self::get_trigger(device)?;
+                self.format.print(
+                    &trigger,
+                    json!({ "trigger": trigger, "device": device.name() }),
+                )
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Active trigger, shown in brackets among the available triggers
+    fn get_trigger(device: &Device) -> Result<String, Error> {
+        let triggers = device.read("trigger")?;
+        triggers
+            .split_whitespace()
+            .find_map(|trigger| trigger.strip_prefix('[')?.strip_suffix(']'))
+            .map(|trigger| trigger.to_string())
+            .ok_or(Error::msg(format!(
+                "Could not find trigger of {}",
+                device.name()
+            )))
+    }
+
+    fn watch(&self, device: &Device) -> Result<()> {
+        if Daemon::new("dashi-backlight-watch")?.is_running()? {
+            return Err(Error::msg("Dashi backlight watcher is already in use"));
+        }
+
+        Device::watch(slice::from_ref(device), |device| {
+            self.show_brightness(device)
+        })
+    }
+
+    fn save(&self, device: &Device) -> Result<()> {
+        let mut levels = Levels::new()?;
+        levels.store(device)?;
+        levels.save()?;
+
+        notify(self.silent, "Backlight", "Saved")?;
+        Ok(())
+    }
+
+    fn restore(&self, device: &Device) -> Result<()> {
+        let raw = Levels::new()?.stored(device).ok_or(Error::msg(
+            "No saved backlight found. Try: dashi backlight save",
+        ))?;
+
+        Fade::acquire(SUBSYSTEM, self.fade)?.run(&Backend::new(), &[(device, raw)])
+    }
+
+    /// Turns the backlight off after `timeout` without input and restores it on the
+    /// next input. Falls back to the logind idle hint when input devices are not readable
+    fn daemon(&self, device: &Device, timeout: Duration) -> Result<()> {
+        if Daemon::new("dashi-backlight")?.is_running()? {
+            return Err(Error::msg("Dashi backlight daemon is already in use"));
+        }
+
+        println!("Dashi backlight daemon started");
+
+        let backend = Backend::new();
+        let (sender, input) = mpsc::channel();
+        let watched = Arc::new(Mutex::new(HashSet::new()));
+        let mut last_input = Instant::now();
+        let mut restore: Option<u32> = None;
+
+        if !Self::watch_input(&sender, &watched) {
+            println!(
+                "Input devices are not readable, using the logind idle hint (e.g. swayidle idlehint)"
+            );
+        }
+
+        loop {
+            // Rescanned every poll so devices plugged in later are watched as well
+            let idle = if Self::watch_input(&sender, &watched) {
+                if input.recv_timeout(IDLE_POLL).is_ok() {
+                    last_input = Instant::now();
+                }
+                input.try_iter().for_each(drop);
+                last_input.elapsed() >= timeout
+            } else {
+                thread::sleep(IDLE_POLL);
+                backend.idle_time()? >= timeout
+            };
+
+            if idle && restore.is_none() {
+                if self.idle.never_on_ac && is_plugged().unwrap_or(false) {
+                    continue;
+                }
+
+                let raw = device.brightness()?;
+                if raw > 0 {
+                    restore = Some(raw);
+                    Fade::acquire(SUBSYSTEM, self.fade)?.run(&backend, &[(device, 0)])?;
+                }
+            } else if !idle && let Some(raw) = restore.take() {
+                Fade::acquire(SUBSYSTEM, self.fade)?.run(&backend, &[(device, raw)])?;
+            }
+        }
+    }
+
+    /// Sends a message for every read from any keyboard or pointer in `/dev/input` not
+    /// yet in `watched`, and returns whether any device is watched
+    fn watch_input(sender: &Sender<()>, watched: &Arc<Mutex<HashSet<PathBuf>>>) -> bool {
+        let entries = fs::read_dir("/dev/input/")
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("event"))
+            .filter(|entry| Self::is_user_input(&entry.file_name().to_string_lossy()));
+
+        for entry in entries {
+            let path = entry.path();
+            if watched.lock().unwrap().contains(&path) {
+                continue;
+            }
+
+            let Ok(mut file) = File::open(&path) else {
+                continue;
+            };
+
+            watched.lock().unwrap().insert(path.clone());
+
+            let sender = sender.clone();
+            let watched = watched.clone();
+            thread::spawn(move || {
+                let mut buffer = [0; 1024];
+                while let Ok(read) = file.read(&mut buffer) {
+                    if read == 0 || sender.send(()).is_err() {
+                        break;
+                    }
+                }
+
+                // Unplugged devices are opened again when they return
+                watched.lock().unwrap().remove(&path);
+            });
+        }
+
+        !watched.lock().unwrap().is_empty()
+    }
+
+    /// Keyboards, mice and touchpads, leaving out switches, hotkey buttons and sensors
+    /// that report events without anyone at the machine
+    fn is_user_input(event: &str) -> bool {
+        let capabilities = Path::new("/sys/class/input/")
+            .join(event)
+            .join("device/capabilities/");
+        let Ok(keys) = fs::read_to_string(capabilities.join("key")) else {
+            return false;
+        };
+
+        let relative = fs::read_to_string(capabilities.join("rel"))
+            .is_ok_and(|rel| rel.split_whitespace().any(|word| word != "0"));
+
+        relative
+            || [KEY_A, BTN_LEFT, BTN_TOUCH]
+                .iter()
+                .any(|&key| has_bit(&keys, key))
+    }
+
+    fn show_brightness(&self, device: &Device) -> Result<()> {
+        let brightness_new = self.get_brightness(device)?;
+
+        self.format.print(
+            format!("{}%", brightness_new),
+            json!({ "backlight": brightness_new, "device": device.name() }),
+        );
 
         if brightness_new == 0 {
             Ok(notify(self.silent, "Backlight", "Minimum")?)
@@ -61,56 +337,47 @@ impl BacklightSpec {
         }
     }
 
-    fn set_brightness(&self, percent: f32) -> Result<()> {
-        let raw = self.get_raw_max_brightness()? as f32;
-        let written = (percent.min(100.0).max(0.0) / 100.0 * raw) as u32;
+    fn change_brightness(&self, device: &Device, change: impl FnOnce(f32) -> f32) -> Result<()> {
+        let fade = Fade::acquire(SUBSYSTEM, self.fade)?;
+        let percent = change(self.get_brightness(device)? as f32).clamp(0.0, 100.0);
+        let raw = device.max_brightness()? as f32;
 
-        Ok(fs::write(
-            self.get_path_brightness()?,
-            format!("{}\0", written),
-        )?)
+        fade.run(&Backend::new(), &[(device, (percent / 100.0 * raw) as u32)])
     }
 
-    fn get_brightness(&self) -> Result<u32, Error> {
-        let num = self.get_raw_brightness()? as f32;
-        let denom = self.get_raw_max_brightness()? as f32;
+    fn get_brightness(&self, device: &Device) -> Result<u32, Error> {
+        let num = device.brightness()? as f32;
+        let denom = device.max_brightness()? as f32;
         let percent = (num / denom * 100.0) as u32;
-        let rounded = (percent + 2) / 5 * 5 as u32;
-        Ok((rounded as u32).min(100))
+        let rounded = (percent + 2) / 5 * 5;
+        Ok(rounded.min(100))
     }
 
-    fn get_raw_brightness(&self) -> Result<u32, Error> {
-        let as_string = fs::read_to_string(self.get_path_brightness()?)?;
-        Ok(as_string[..as_string.len() - 1].parse::<u32>()?)
-    }
+    fn get_device(&self) -> Result<Device, Error> {
+        let mut devices = Device::list(SUBSYSTEM)?.into_iter();
 
-    fn get_raw_max_brightness(&self) -> Result<u32, Error> {
-        let as_string = fs::read_to_string(self.get_path_max_brightness()?)?;
-        Ok(as_string[..as_string.len() - 1].parse::<u32>()?)
-    }
-
-    fn get_path_system(&self) -> Result<PathBuf, Error> {
-        Path::new("/sys/class/leds/")
-            .read_dir()
-            .map_err(|err| Error::new(err))?
-            .filter_map(|device| device.ok())
-            .filter(|device| {
-                device
-                    .file_name()
-                    .to_str()
-                    .map(|str| str.contains("backlight"))
-                    .unwrap_or(false)
-            })
-            .last()
-            .map(|entry| entry.path())
-            .ok_or_else(|| Error::msg("Could not find device in /sys/class/leds/"))
-    }
-
-    fn get_path_brightness(&self) -> Result<PathBuf, Error> {
-        Ok(self.get_path_system()?.join("brightness"))
+        match &self.device {
+            Some(name) => devices.find(|device| device.name() == name).ok_or_else(|| {
+                Error::msg(format!(
+                    "Could not find device {} in /sys/class/leds/",
+                    name
+                ))
+            }),
+            None => devices
+                .rfind(|device| device.name().contains("backlight"))
+                .ok_or_else(|| Error::msg("Could not find device in /sys/class/leds/")),
+        }
     }
+}
 
-    fn get_path_max_brightness(&self) -> Result<PathBuf, Error> {
-        Ok(self.get_path_system()?.join("max_brightness"))
-    }
+/// Checks a bit of a sysfs capability bitmap, written as hex words of the kernel's
+/// `long` with the most significant word first
+fn has_bit(bitmap: &str, bit: usize) -> bool {
+    let bits = usize::BITS as usize;
+    bitmap
+        .split_whitespace()
+        .rev()
+        .nth(bit / bits)
+        .and_then(|word| usize::from_str_radix(word, 16).ok())
+        .is_some_and(|word| word & (1 << (bit % bits)) != 0)
 }
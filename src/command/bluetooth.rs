@@ -1,5 +1,6 @@
-use crate::{command::BluetoothCommand, notify::notify};
+use crate::{command::BluetoothCommand, format::Format, notify::notify};
 use color_eyre::{eyre::Error, Result};
+use serde_json::json;
 use zbus::{
     blocking::{Connection, Proxy},
     zvariant::OwnedObjectPath,
@@ -7,6 +8,7 @@ use zbus::{
 
 pub struct BluetoothSpec {
     silent: bool,
+    format: Format,
 }
 
 // Dbus Docs: https://www.freedesktop.org/wiki/Software/systemd/dbus/
@@ -15,8 +17,8 @@ const SERVICE: &'static str = "bluetooth.service";
 const TOGGLE_MODE: &'static str = "replace";
 
 impl BluetoothSpec {
-    pub fn new(silent: bool) -> Self {
-        BluetoothSpec { silent }
+    pub fn new(silent: bool, format: Format) -> Self {
+        BluetoothSpec { silent, format }
     }
 
     pub fn run(&self, modifier: BluetoothCommand) -> Result<()> {
@@ -99,10 +101,10 @@ impl BluetoothSpec {
     fn feedback(&self, enabled: bool) -> Result<()> {
         if enabled {
             notify(self.silent, "Bluetooth", "Enabled")?;
-            println!("{}", "Enabled");
+            self.format.print("Enabled", json!({ "enabled": true }));
         } else {
             notify(self.silent, "Bluetooth", "Disabled")?;
-            println!("{}", "Disabled");
+            self.format.print("Disabled", json!({ "enabled": false }));
         }
 
         Ok(())
@@ -1,35 +1,110 @@
-use std::{
-    fs,
-    path::{Path, PathBuf},
-};
+use std::{path::Path, thread, time::Duration};
 
 use color_eyre::{eyre::Error, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 
-use crate::{command::BrightnessCommand, notify::notify};
+use crate::{
+    command::{BrightnessAutoCommand, BrightnessCommand},
+    config::{AutoBrightnessConfig, BrightnessConfig, Curve},
+    daemon::Daemon,
+    format::Format,
+    io::{load_json, save_json},
+    levels::Levels,
+    notify::notify,
+    sysfs::{Backend, Device, Fade},
+};
 
 pub struct BrightnessSpec {
     silent: bool,
+    format: Format,
+    device: Option<String>,
+    all: bool,
+    fade: Duration,
+    curve: Curve,
+    gamma: f32,
+    step: u32,
+    auto: AutoBrightnessConfig,
+}
+
+/// Offset learned from manual changes while automatic brightness is running
+#[derive(Deserialize, Serialize, Default)]
+struct AutoState {
+    offset: f32,
 }
 
+const SUBSYSTEM: &str = "backlight";
+const AUTO_DAEMON: &str = "dashi-brightness-auto";
+const AUTO_SMOOTHING: f32 = 0.2;
+
 impl BrightnessSpec {
-    pub fn new(silent: bool) -> Self {
-        BrightnessSpec { silent }
+    pub fn new(
+        silent: bool,
+        format: Format,
+        device: Option<String>,
+        all: bool,
+        fade: u64,
+        config: BrightnessConfig,
+    ) -> Self {
+        BrightnessSpec {
+            silent,
+            format,
+            device,
+            all,
+            fade: Duration::from_millis(fade),
+            curve: config.curve,
+            gamma: config.gamma.max(f32::EPSILON),
+            step: config.step.clamp(1, 100),
+            auto: config.auto,
+        }
     }
 
     pub fn run(&self, modifier: BrightnessCommand) -> Result<()> {
+        match modifier {
+            BrightnessCommand::List => return self.list(),
+            BrightnessCommand::Auto { modifier } => return self.auto(modifier),
+            _ => {}
+        }
+
+        let changed = matches!(
+            modifier,
+            BrightnessCommand::Add { .. }
+                | BrightnessCommand::Sub { .. }
+                | BrightnessCommand::Set { .. }
+        );
+        let saving = matches!(
+            modifier,
+            BrightnessCommand::Save | BrightnessCommand::Restore
+        );
+
+        let devices = self.get_devices(self.all || saving)?;
+        let primary = &devices[0];
+
         let result = match modifier {
             BrightnessCommand::Add { n } => {
-                self.set_brightness(self.get_brightness()? as f32 + n as f32)
+                self.change_brightness(&devices, |percent| percent + n as f32)
             }
             BrightnessCommand::Sub { n } => {
-                self.set_brightness(self.get_brightness()? as f32 - n as f32)
+                self.change_brightness(&devices, |percent| percent - n as f32)
+            }
+            BrightnessCommand::Set { n } => self.change_brightness(&devices, |_| n as f32),
+            BrightnessCommand::Save => return self.save(&devices),
+            BrightnessCommand::Watch => return self.watch(&devices),
+            BrightnessCommand::Restore => {
+                self.restore(&devices)?;
+                return self.show_brightness(primary);
+            }
+            BrightnessCommand::Get | BrightnessCommand::List | BrightnessCommand::Auto { .. } => {
+                Ok(())
             }
-            BrightnessCommand::Set { n } => self.set_brightness((n as f32).min(100.0).max(1.0)),
-            BrightnessCommand::Get => Ok(()),
         };
 
+        if changed && result.is_ok() {
+            Levels::remember_current(&devices);
+        }
+
         match result {
-            Ok(_) => self.show_brightness()?,
+            Ok(_) => self.show_brightness(primary)?,
             Err(_) => {
                 notify(
                     false,
@@ -37,8 +112,8 @@ impl BrightnessSpec {
                     "Brightness cannot be modified. See documentation for more information",
                 )?;
                 eprintln!(
-                    r#"Brightness file is not writable without giving the current user access. See
-                    https://github.com/nate-craft/dashi for more information"#
+                    r#"Brightness could not be set through systemd-logind and the brightness file is not
+                    writable by the current user. See https://github.com/nate-craft/dashi for more information"#
                 );
             }
         }
@@ -46,8 +121,162 @@ impl BrightnessSpec {
         result
     }
 
-    fn show_brightness(&self) -> Result<()> {
-        let brightness_new = self.get_brightness()?;
+    fn list(&self) -> Result<()> {
+        let mut plain = Vec::new();
+        let mut devices = Vec::new();
+
+        for device in self.get_all_devices()? {
+            let kind = device
+                .read("type")
+                .unwrap_or_else(|_| "unknown".to_string());
+            let brightness = self.get_brightness(&device)?;
+
+            plain.push(format!("{} ({}): {}%", device.name(), kind, brightness));
+            devices
+                .push(json!({ "device": device.name(), "type": kind, "brightness": brightness }));
+        }
+
+        self.format.print(plain.join("\n"), json!(devices));
+        Ok(())
+    }
+
+    fn watch(&self, devices: &[Device]) -> Result<()> {
+        if Daemon::new("dashi-brightness-watch")?.is_running()? {
+            return Err(Error::msg("Dashi brightness watcher is already in use"));
+        }
+
+        Device::watch(devices, |device| self.show_brightness(device))
+    }
+
+    fn save(&self, devices: &[Device]) -> Result<()> {
+        let mut levels = Levels::new()?;
+        for device in devices {
+            levels.store(device)?;
+        }
+        levels.save()?;
+
+        notify(self.silent, "Brightness", "Saved")?;
+        Ok(())
+    }
+
+    fn restore(&self, devices: &[Device]) -> Result<()> {
+        let levels = Levels::new()?;
+        let targets = devices
+            .iter()
+            .filter_map(|device| levels.stored(device).map(|raw| (device, raw)))
+            .collect::<Vec<(&Device, u32)>>();
+
+        if targets.is_empty() {
+            return Err(Error::msg(
+                "No saved brightness found. Try: dashi brightness save",
+            ));
+        }
+
+        Fade::acquire(SUBSYSTEM, self.fade)?.run(&Backend::new(), &targets)
+    }
+
+    fn auto(&self, modifier: BrightnessAutoCommand) -> Result<()> {
+        let daemon = Daemon::new(AUTO_DAEMON)?;
+
+        match modifier {
+            BrightnessAutoCommand::Start => {
+                if daemon.is_running()? {
+                    return Err(Error::msg("Dashi brightness daemon is already in use"));
+                }
+
+                daemon.listen()?;
+                self.auto_feedback(true)?;
+                self.auto_daemon(&daemon)
+            }
+            BrightnessAutoCommand::Stop => {
+                if daemon.is_running()? {
+                    daemon.interrupt()?;
+                }
+
+                self.auto_feedback(false)
+            }
+            BrightnessAutoCommand::Status => self.auto_feedback(daemon.is_running()?),
+        }
+    }
+
+    /// Follows the ambient light sensor until interrupted by `auto stop`. Manual changes
+    /// made in the meantime are kept as an offset on top of the ambient brightness
+    fn auto_daemon(&self, daemon: &Daemon) -> Result<()> {
+        let sensor = Self::get_sensor()?;
+        let devices = self.get_devices(self.all)?;
+        let mut state = AutoState::new()?;
+        let mut lux = Self::get_lux(&sensor)?;
+        let mut last = None;
+
+        while !daemon.is_interrupted() {
+            lux += (Self::get_lux(&sensor)? - lux) * AUTO_SMOOTHING;
+
+            let ambient = self.get_ambient_brightness(lux);
+            let current = self.get_brightness(&devices[0])?;
+
+            if let Some(last) = last
+                && last != current
+            {
+                state.offset = current as f32 - ambient;
+                state.save()?;
+            }
+
+            let target = (ambient + state.offset).clamp(1.0, 100.0);
+            if (target - current as f32).abs() >= self.step as f32 {
+                self.change_brightness(&devices, |_| target)?;
+            }
+
+            last = Some(self.get_brightness(&devices[0])?);
+            thread::sleep(Duration::from_millis(self.auto.interval));
+        }
+
+        Ok(())
+    }
+
+    fn auto_feedback(&self, enabled: bool) -> Result<()> {
+        let body = if enabled { "On" } else { "Off" };
+
+        notify(self.silent, "Auto Brightness", body)?;
+        self.format.print(body, json!({ "enabled": enabled }));
+
+        Ok(())
+    }
+
+    fn get_ambient_brightness(&self, lux: f32) -> f32 {
+        let max_lux = self.auto.max_lux.max(1.0);
+        let fraction = ((lux.max(0.0) + 1.0).log10() / (max_lux + 1.0).log10()).min(1.0);
+
+        self.auto.min + (100.0 - self.auto.min) * fraction
+    }
+
+    fn get_lux(sensor: &Device) -> Result<f32, Error> {
+        if let Ok(lux) = sensor.read_f32("in_illuminance_input") {
+            return Ok(lux);
+        }
+
+        let raw = sensor.read_f32("in_illuminance_raw")?;
+        let offset = sensor.read_f32("in_illuminance_offset").unwrap_or(0.0);
+        let scale = sensor.read_f32("in_illuminance_scale").unwrap_or(1.0);
+
+        Ok((raw + offset) * scale)
+    }
+
+    fn get_sensor() -> Result<Device, Error> {
+        Device::list_in("iio", Path::new("/sys/bus/iio/devices/"))?
+            .into_iter()
+            .find(|device| Self::get_lux(device).is_ok())
+            .ok_or(Error::msg(
+                "Could not find an ambient light sensor in /sys/bus/iio/devices",
+            ))
+    }
+
+    fn show_brightness(&self, device: &Device) -> Result<()> {
+        let brightness_new = self.get_brightness(device)?;
+
+        self.format.print(
+            format!("{}%", brightness_new),
+            json!({ "brightness": brightness_new, "device": device.name() }),
+        );
 
         if brightness_new == 0 {
             Ok(notify(self.silent, "Brightness", "Minimum")?)
@@ -60,49 +289,205 @@ impl BrightnessSpec {
         }
     }
 
-    fn set_brightness(&self, percent: f32) -> Result<()> {
-        let raw = self.get_raw_max_brightness()? as f32;
-        let written = (percent.min(100.0).max(1.0) / 100.0 * raw) as u32;
+    fn change_brightness(&self, devices: &[Device], change: impl FnOnce(f32) -> f32) -> Result<()> {
+        let fade = Fade::acquire(SUBSYSTEM, self.fade)?;
+        let current = self.get_brightness(&devices[0])?;
+        let min = self.min_percent(devices[0].max_brightness()?);
+        let percent = self
+            .snap(current, change(current as f32))
+            .clamp(min as f32, 100.0);
+
+        let targets = devices
+            .iter()
+            .map(|device| {
+                Ok((
+                    device,
+                    self.to_raw(percent, device.max_brightness()?).max(1),
+                ))
+            })
+            .collect::<Result<Vec<(&Device, u32)>, Error>>()?;
 
-        Ok(fs::write(
-            self.get_path_brightness()?,
-            format!("{}\0", written),
-        )?)
+        fade.run(&Backend::new(), &targets)
     }
 
-    fn get_brightness(&self) -> Result<u32, Error> {
-        let num = self.get_raw_brightness()? as f32;
-        let denom = self.get_raw_max_brightness()? as f32;
-        let percent = (num / denom * 100.0) as u32;
-        let rounded = (percent + 2) / 5 * 5 as u32;
+    fn get_brightness(&self, device: &Device) -> Result<u32, Error> {
+        Ok(self.percent_of(device.brightness()?, device.max_brightness()?))
+    }
 
-        Ok((rounded as u32).min(100))
+    /// Step shown for a raw value, preferring the steps that `to_raw` maps to it, so `get`
+    /// returns what `set` was given unless several steps share the raw value
+    fn percent_of(&self, raw: u32, max: u32) -> u32 {
+        if raw == 0 {
+            return 0;
+        }
+
+        let percent = self.to_percent(raw, max);
+        let min = self.min_percent(max);
+
+        (min / self.step..=100 / self.step)
+            .map(|steps| steps * self.step)
+            .filter(|step| self.to_raw(*step as f32, max) == raw)
+            .min_by(|a, b| {
+                (*a as f32 - percent)
+                    .abs()
+                    .total_cmp(&(*b as f32 - percent).abs())
+            })
+            .unwrap_or_else(|| {
+                ((percent / self.step as f32).round() as u32 * self.step).clamp(min, 100)
+            })
     }
 
-    fn get_raw_brightness(&self) -> Result<u32, Error> {
-        let as_string = fs::read_to_string(self.get_path_brightness()?)?;
-        Ok(as_string[..as_string.len() - 1].parse::<u32>()?)
+    /// Lowest step that maps to a raw value above zero, since the curve rounds lower
+    /// steps to the same raw value and `get` could not return what `set` was given
+    fn min_percent(&self, max: u32) -> u32 {
+        (1..=100 / self.step)
+            .map(|steps| steps * self.step)
+            .find(|percent| self.to_raw(*percent as f32, max) >= 1)
+            .unwrap_or(100)
     }
 
-    fn get_raw_max_brightness(&self) -> Result<u32, Error> {
-        let as_string = fs::read_to_string(self.get_path_max_brightness()?)?;
-        Ok(as_string[..as_string.len() - 1].parse::<u32>()?)
+    /// Rounds to the configured step in the direction of the change, so changes
+    /// smaller than a step still move brightness
+    fn snap(&self, current: u32, requested: f32) -> f32 {
+        let steps = requested / self.step as f32;
+
+        if requested > current as f32 {
+            steps.ceil() * self.step as f32
+        } else {
+            steps.floor() * self.step as f32
+        }
     }
 
-    fn get_path_system(&self) -> Result<PathBuf, Error> {
-        Ok(Path::new("/sys/class/backlight/")
-            .read_dir()
-            .map_err(|err| Error::new(err))?
-            .last()
-            .ok_or(Error::msg("Could not find device in /sys/class/backlight"))?
-            .map(|entry| entry.path())?)
+    fn to_raw(&self, percent: f32, max: u32) -> u32 {
+        let fraction = match self.curve {
+            Curve::Linear => percent / 100.0,
+            Curve::Exponential => (percent / 100.0).powf(self.gamma),
+        };
+
+        (fraction * max as f32).round() as u32
+    }
+
+    fn to_percent(&self, raw: u32, max: u32) -> f32 {
+        let fraction = raw as f32 / max as f32;
+
+        match self.curve {
+            Curve::Linear => fraction * 100.0,
+            Curve::Exponential => fraction.powf(1.0 / self.gamma) * 100.0,
+        }
     }
 
-    fn get_path_brightness(&self) -> Result<PathBuf, Error> {
-        Ok(self.get_path_system()?.join("brightness"))
+    /// Selected device, otherwise the preferred device or every device
+    fn get_devices(&self, all: bool) -> Result<Vec<Device>, Error> {
+        let mut devices = self.get_all_devices()?;
+
+        if let Some(name) = &self.device {
+            devices.retain(|device| device.name() == name);
+            if devices.is_empty() {
+                return Err(Error::msg(format!(
+                    "Could not find device {} in /sys/class/backlight",
+                    name
+                )));
+            }
+        } else if !all {
+            devices.truncate(1);
+        }
+
+        if devices.is_empty() {
+            Err(Error::msg("Could not find device in /sys/class/backlight"))
+        } else {
+            Ok(devices)
+        }
+    }
+
+    /// Devices sorted by the kernel's preference order (firmware > platform > raw)
+    fn get_all_devices(&self) -> Result<Vec<Device>, Error> {
+        let mut devices = Device::list(SUBSYSTEM)?;
+        devices.sort_by_key(|device| match device.read("type").as_deref() {
+            Ok("firmware") => 0,
+            Ok("platform") => 1,
+            Ok("raw") => 2,
+            _ => 3,
+        });
+
+        Ok(devices)
+    }
+}
+
+impl AutoState {
+    fn new() -> Result<AutoState, Error> {
+        load_json("brightness-auto.json")
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        save_json("brightness-auto.json", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(curve: Curve) -> BrightnessSpec {
+        let config = BrightnessConfig {
+            curve,
+            ..BrightnessConfig::default()
+        };
+
+        BrightnessSpec::new(false, Format::Plain, None, false, 0, config)
+    }
+
+    /// Raw value written by `set`
+    fn set(spec: &BrightnessSpec, percent: u32, max: u32) -> u32 {
+        let percent = percent.max(spec.min_percent(max));
+        spec.to_raw(percent as f32, max).max(1)
+    }
+
+    #[test]
+    fn get_returns_set_value() {
+        for curve in [Curve::Linear, Curve::Exponential] {
+            let spec = spec(curve);
+            for max in [255, 1000, 19393] {
+                for percent in (spec.min_percent(max)..=100).step_by(5) {
+                    assert_eq!(spec.percent_of(set(&spec, percent, max), max), percent);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn get_maps_back_to_same_raw_at_small_max() {
+        for curve in [Curve::Linear, Curve::Exponential] {
+            let spec = spec(curve);
+            for max in [1, 3, 7, 15, 96] {
+                for percent in (5..=100).step_by(5) {
+                    let raw = set(&spec, percent, max);
+                    let shown = spec.percent_of(raw, max);
+
+                    assert_eq!(set(&spec, shown, max), raw);
+                    assert!(shown >= spec.min_percent(max));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn min_percent_is_lowest_visible_step() {
+        let linear = spec(Curve::Linear);
+        assert_eq!(linear.min_percent(1000), 5);
+        assert_eq!(linear.min_percent(3), 20);
+
+        let exponential = spec(Curve::Exponential);
+        assert_eq!(exponential.min_percent(96), 10);
+        assert_eq!(exponential.to_raw(5.0, 96), 0);
+        assert_eq!(exponential.percent_of(0, 96), 0);
     }
 
-    fn get_path_max_brightness(&self) -> Result<PathBuf, Error> {
-        Ok(self.get_path_system()?.join("max_brightness"))
+    #[test]
+    fn snap_moves_by_at_least_one_step() {
+        let spec = spec(Curve::Linear);
+        assert_eq!(spec.snap(50, 51.0), 55.0);
+        assert_eq!(spec.snap(50, 49.0), 45.0);
+        assert_eq!(spec.snap(50, 60.0), 60.0);
+        assert_eq!(spec.snap(50, 50.0), 50.0);
     }
 }
@@ -1,4 +1,4 @@
-use clap::Subcommand;
+use clap::{value_parser, Subcommand, ValueEnum};
 
 pub mod backlight;
 pub mod bluetooth;
@@ -11,14 +11,30 @@ pub mod volume;
 #[derive(Subcommand)]
 pub enum Command {
     Brightness {
+        #[arg(short, long, global = true)]
+        device: Option<String>,
+        #[arg(short, long, global = true, conflicts_with = "device")]
+        all: bool,
+        #[arg(short, long, global = true, value_name = "MS")]
+        fade: Option<u64>,
         #[command(subcommand)]
         modifier: BrightnessCommand,
     },
     Backlight {
+        #[arg(short, long, global = true)]
+        device: Option<String>,
+        #[arg(short, long, global = true, value_name = "MS")]
+        fade: Option<u64>,
         #[command(subcommand)]
         modifier: BacklightCommand,
     },
     Volume {
+        #[arg(long, global = true)]
+        sink: Option<String>,
+        #[arg(long, global = true)]
+        source: Option<String>,
+        #[arg(long, global = true, value_enum, default_value_t)]
+        backend: VolumeBackend,
         #[command(subcommand)]
         modifier: VolumeCommand,
     },
@@ -59,6 +75,94 @@ pub enum VolumeCommand {
     MutedMic,
     Mute,
     MuteMic,
+    Sinks,
+    Sources,
+    Default {
+        #[arg(value_enum)]
+        sink: String,
+    },
+    NextSink,
+    Apps,
+    App {
+        #[arg(value_enum)]
+        target: String,
+        #[command(subcommand)]
+        modifier: AppCommand,
+    },
+    Mic {
+        #[command(subcommand)]
+        modifier: MicCommand,
+    },
+    Balance {
+        #[arg(allow_negative_numbers = true, value_parser = value_parser!(i32).range(-100..=100))]
+        n: Option<i32>,
+    },
+    Watch,
+    Profiles {
+        #[arg(value_enum)]
+        card: Option<String>,
+    },
+    Profile {
+        #[arg(value_enum)]
+        card: String,
+        #[arg(value_enum)]
+        profile: String,
+    },
+    ToggleHeadset,
+    Daemon,
+    Ptt {
+        #[command(subcommand)]
+        modifier: PttCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PttCommand {
+    Press,
+    Release,
+}
+
+#[derive(ValueEnum, Clone, Copy, Default)]
+pub enum VolumeBackend {
+    #[default]
+    Auto,
+    Pulse,
+    Pipewire,
+    Alsa,
+}
+
+#[derive(Subcommand)]
+pub enum AppCommand {
+    Add {
+        #[arg(value_enum)]
+        n: u32,
+    },
+    Sub {
+        #[arg(value_enum)]
+        n: u32,
+    },
+    Set {
+        #[arg(value_enum)]
+        n: u32,
+    },
+    Mute,
+}
+
+#[derive(Subcommand)]
+pub enum MicCommand {
+    Add {
+        #[arg(value_enum)]
+        n: u32,
+    },
+    Sub {
+        #[arg(value_enum)]
+        n: u32,
+    },
+    Set {
+        #[arg(value_enum)]
+        n: u32,
+    },
+    Get,
 }
 
 #[derive(Subcommand)]
@@ -76,6 +180,21 @@ pub enum BrightnessCommand {
         n: u32,
     },
     Get,
+    List,
+    Save,
+    Restore,
+    Watch,
+    Auto {
+        #[command(subcommand)]
+        modifier: BrightnessAutoCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum BrightnessAutoCommand {
+    Start,
+    Stop,
+    Status,
 }
 
 #[derive(Subcommand)]
@@ -93,6 +212,18 @@ pub enum BacklightCommand {
         n: u32,
     },
     Get,
+    List,
+    Trigger {
+        #[arg(value_enum)]
+        trigger: Option<String>,
+    },
+    Save,
+    Restore,
+    Watch,
+    Daemon {
+        #[arg(short, long, value_name = "SECONDS")]
+        timeout: Option<u64>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -1,17 +1,19 @@
 use std::process::Command;
 
-use crate::{command::NightShiftCommand, daemon::Daemon, notify::notify};
+use crate::{command::NightShiftCommand, daemon::Daemon, format::Format, notify::notify};
 use color_eyre::{eyre::Error, Result};
+use serde_json::json;
 
 pub struct NightShiftSpec {
     silent: bool,
+    format: Format,
 }
 
 const SHIFT_COMMAND: &'static str = "gammastep";
 
 impl NightShiftSpec {
-    pub fn new(silent: bool) -> Self {
-        Self { silent }
+    pub fn new(silent: bool, format: Format) -> Self {
+        Self { silent, format }
     }
 
     pub fn run(&self, modifier: NightShiftCommand) -> Result<()> {
@@ -73,10 +75,10 @@ impl NightShiftSpec {
     fn feedback(&self, enabled: bool) -> Result<()> {
         if enabled {
             notify(self.silent, "Nightshift", "On")?;
-            println!("{}", "On");
+            self.format.print("On", json!({ "enabled": true }));
         } else {
             notify(self.silent, "Nightshift", "Off")?;
-            println!("{}", "Off");
+            self.format.print("Off", json!({ "enabled": false }));
         }
 
         Ok(())
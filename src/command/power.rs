@@ -1,45 +1,62 @@
-use std::{fs, path::Path, thread, time::Duration};
+use std::{
+    fs,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
 use color_eyre::{eyre::Error, Result};
-use crate::{command::PowerCommand, daemon::Daemon, notify::notify};
+use serde_json::json;
+use crate::{command::PowerCommand, daemon::Daemon, format::Format, levels::Levels, notify::notify};
 
 pub struct PowerSpec {
     silent: bool,
+    format: Format,
 }
 
 const PATH_CAPACITY: &'static str = "/sys/class/power_supply/BAT0/capacity";
 const PATH_PLUGGED: &'static str = "/sys/class/power_supply/ADP1/online";
+const POLL_PLUGGED: Duration = Duration::from_secs(5);
+const POLL_CAPACITY: Duration = Duration::from_mins(5);
+
+pub fn is_plugged() -> Result<bool> {
+    let string = fs::read_to_string(Path::new(PATH_PLUGGED))?;
+    Ok(string[..string.len() - 1].parse::<i32>()? != 0)
+}
 
 impl PowerSpec {
-    pub fn new(silent: bool) -> Self {
-        Self { silent }
+    pub fn new(silent: bool, format: Format) -> Self {
+        Self { silent, format }
     }
 
     pub fn run(&self, modifier: PowerCommand) -> Result<()> {
         match modifier {
             PowerCommand::Level => {
                 let capacity = self.capacity()?;
-                println!("{}", capacity);
+                self.format.print(capacity, json!({ "capacity": capacity }));
                 notify(self.silent, "Battery", capacity.to_string())?;
             }
             PowerCommand::Plugged => {
-                let status = if self.is_plugged()? {
+                let plugged = is_plugged()?;
+                let status = if plugged {
                     "Charging"
                 } else {
                     "Discharging"
                 };
 
-                println!("{}", status);
+                self.format.print(status, json!({ "plugged": plugged }));
                 notify(self.silent, "Battery Status", status)?;
             }
             PowerCommand::Info => {
                 let capacity = self.capacity()?;
                 let capacity_str = format!("{}%", capacity);
+                let plugged = is_plugged()?;
+                let json = json!({ "capacity": capacity, "plugged": plugged });
 
-                if self.is_plugged()? || capacity > 20 {
-                    println!("Battery: {}", &capacity_str);
+                if plugged || capacity > 20 {
+                    self.format.print(format!("Battery: {}", &capacity_str), json);
                     notify(self.silent, "Battery", &capacity_str)?;
                 } else {
-                    println!("Low Battery: {}", &capacity_str);
+                    self.format.print(format!("Low Battery: {}", &capacity_str), json);
                     notify(self.silent, "Low Battery", &capacity_str)?;
                 }
             }
@@ -56,17 +73,30 @@ impl PowerSpec {
 
         println!("Dashi battery daemon started");
 
+        let mut plugged = is_plugged().ok();
+        let mut checked: Option<Instant> = None;
+
         loop {
-            if let Ok(capacity) = self.capacity() && !self.is_plugged()? && self.capacity()? < 20 {
-                notify(self.silent, "Low Battery", format!("{}%", capacity))?;
+            let plugged_now = is_plugged().ok();
+            if plugged_now != plugged
+                && let Some(plugged_now) = plugged_now
+            {
+                // Brightness levels remembered for the new power source
+                if let Err(err) = Levels::new().and_then(|levels| levels.apply(plugged_now)) {
+                    eprintln!("Could not restore brightness: {}", err);
+                }
             }
-            thread::sleep(Duration::from_mins(5));
-        }
-    }
+            plugged = plugged_now;
 
-    fn is_plugged(&self) -> Result<bool> {
-        let string = fs::read_to_string(Path::new(PATH_PLUGGED))?;
-        Ok(string[..string.len() - 1].parse::<i32>()? != 0)
+            if checked.is_none_or(|checked| checked.elapsed() >= POLL_CAPACITY) {
+                if let Ok(capacity) = self.capacity() && !is_plugged()? && self.capacity()? < 20 {
+                    notify(self.silent, "Low Battery", format!("{}%", capacity))?;
+                }
+                checked = Some(Instant::now());
+            }
+
+            thread::sleep(POLL_PLUGGED);
+        }
     }
 
     fn capacity(&self) -> Result<i32> {
@@ -1,38 +1,153 @@
 use color_eyre::eyre::Error;
 use color_eyre::Result;
 use pulse::callbacks::ListResult;
+use pulse::channelmap::Map as ChannelMap;
+use pulse::context::subscribe::{Facility, InterestMaskSet, Operation};
 use pulse::context::State as PaState;
+use pulse::def::PortAvailable;
+use pulse::proplist::properties;
 use pulse::volume::{ChannelVolumes, Volume as PaVolume};
 use pulse::{
     context::{Context, FlagSet},
     error::{Code, PAErr},
     mainloop::standard::{IterateResult, Mainloop},
 };
+use serde_json::json;
+use std::cell::{Cell, RefCell};
+use std::cmp::Reverse;
 use std::fmt::Display;
-use std::ops::{Add, Sub};
+use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 
+use crate::config::VolumeConfig;
+use crate::daemon::Daemon;
+use crate::format::Format;
 use crate::notify::notify;
+use crate::sysfs::{Backend, Device};
 
-use super::VolumeCommand;
+use memory::Memory;
 
-const MAX_VOLUME: i32 = 150;
+use super::{AppCommand, MicCommand, PttCommand, VolumeBackend, VolumeCommand};
+
+mod alsa;
+mod click;
+mod memory;
+mod pipewire;
 
 pub struct VolumeSpec {
     silent: bool,
+    format: Format,
+    sink: String,
+    source: String,
+    backend: VolumeBackend,
+    config: VolumeConfig,
 }
 
 #[derive(Default, Clone, Copy)]
 struct DashiVolume(i32);
 
+/// Ceiling and rounding step in percent
+#[derive(Clone, Copy)]
+struct VolumeLimits {
+    max: i32,
+    step: i32,
+}
+
+/// State of a sink or source
+struct PaDevice {
+    index: u32,
+    name: String,
+    channels: ChannelVolumes,
+    channel_map: ChannelMap,
+    volume: DashiVolume,
+    muted: bool,
+}
+
+/// Sink or source as listed by `volume sinks` and `volume sources`
+struct PaEntry {
+    index: u32,
+    name: String,
+    description: String,
+    state: String,
+    available: bool,
+}
+
+/// Application stream playing on a sink
+struct PaApp {
+    index: u32,
+    name: String,
+    binary: String,
+    pid: Option<u32>,
+    channels: ChannelVolumes,
+    volume: DashiVolume,
+    muted: bool,
+}
+
+/// Card with its profiles as listed by `volume profiles`
+struct PaCard {
+    index: u32,
+    name: String,
+    description: String,
+    profiles: Vec<PaProfile>,
+    active: String,
+}
+
+struct PaProfile {
+    name: String,
+    description: String,
+    priority: u32,
+    available: bool,
+}
+
+/// Sinks and history tracked by `volume daemon` between events
+struct DaemonState {
+    sinks: Vec<PaEntry>,
+    default: String,
+    history: Vec<String>,
+    auto_muted: bool,
+}
+
+const DEFAULT_SINK: &str = "@DEFAULT_SINK@";
+const DEFAULT_SOURCE: &str = "@DEFAULT_SOURCE@";
+const MUTE_LED: &str = "::mute";
+const MICMUTE_LED: &str = "::micmute";
+
 struct PaFeedback<T>(Arc<Mutex<Option<Result<T, PAErr>>>>);
 
 impl VolumeSpec {
-    pub fn new(silent: bool) -> Self {
-        VolumeSpec { silent }
+    pub fn new(
+        silent: bool,
+        format: Format,
+        sink: Option<String>,
+        source: Option<String>,
+        backend: VolumeBackend,
+        config: VolumeConfig,
+    ) -> Self {
+        VolumeSpec {
+            silent,
+            format,
+            sink: sink.unwrap_or(DEFAULT_SINK.to_string()),
+            source: source.unwrap_or(DEFAULT_SOURCE.to_string()),
+            backend,
+            config,
+        }
     }
 
     pub fn run(&self, modifier: VolumeCommand) -> Result<(), Error> {
+        match self.backend {
+            VolumeBackend::Pulse => self.run_pulse(Self::connect()?, modifier),
+            VolumeBackend::Pipewire => self.run_pipewire(modifier),
+            VolumeBackend::Alsa => self.run_alsa(modifier),
+            VolumeBackend::Auto => match Self::connect() {
+                Ok(connection) => self.run_pulse(connection, modifier),
+                Err(_) if pipewire::is_available() => self.run_pipewire(modifier),
+                Err(_) if alsa::is_available() => self.run_alsa(modifier),
+                Err(err) => Err(err),
+            },
+        }
+    }
+
+    fn connect() -> Result<(Mainloop, Context), Error> {
         let mut pulse = Mainloop::new().ok_or(PAErr::from(Code::Access))?;
         let mut context = Context::new(&pulse, "dashi").ok_or(PAErr::from(Code::Access))?;
 
@@ -55,66 +170,815 @@ impl VolumeSpec {
             }
         }
 
+        Ok((pulse, context))
+    }
+
+    fn run_pulse(
+        &self,
+        (mut pulse, mut context): (Mainloop, Context),
+        modifier: VolumeCommand,
+    ) -> Result<(), Error> {
+        // The daemon restores on its own, commands keep working without a writable data dir
+        if let Err(err) = self.restore(&mut pulse, &mut context) {
+            eprintln!("Could not restore the sink volume: {}", err);
+        }
+
         match modifier {
             VolumeCommand::Add { n } => {
-                let (mut channels, mut volume, _) = self.get_output(&mut pulse, &mut context)?;
-                volume = volume + n.into();
-                self.set_volume(&mut pulse, &mut context, &mut channels, volume)?;
-                notify(self.silent, "Volume", format!("{}%", volume))?;
+                let mut output = self.get_output(&mut pulse, &mut context)?;
+                output.volume = output
+                    .volume
+                    .change(n as i32, self.limits(Some(&output.name)));
+                self.set_volume(&mut pulse, &mut context, &mut output)?;
+                self.click(&mut pulse, &mut context, &output.name);
+                self.remember(&output);
+                self.feedback_output(&output.name, output.volume, output.muted, false)?;
             }
             VolumeCommand::Sub { n } => {
-                let (mut channels, mut volume, _) = self.get_output(&mut pulse, &mut context)?;
-                volume = volume - n.into();
-                self.set_volume(&mut pulse, &mut context, &mut channels, volume)?;
-                notify(self.silent, "Volume", format!("{}%", volume))?;
+                let mut output = self.get_output(&mut pulse, &mut context)?;
+                output.volume = output
+                    .volume
+                    .change(-(n as i32), self.limits(Some(&output.name)));
+                self.set_volume(&mut pulse, &mut context, &mut output)?;
+                self.click(&mut pulse, &mut context, &output.name);
+                self.remember(&output);
+                self.feedback_output(&output.name, output.volume, output.muted, false)?;
             }
             VolumeCommand::Set { n } => {
-                let (mut channels, _, _) = self.get_output(&mut pulse, &mut context)?;
-                let volume = n.into();
-                self.set_volume(&mut pulse, &mut context, &mut channels, volume)?;
-                notify(self.silent, "Volume", format!("{}%", volume))?;
+                let mut output = self.get_output(&mut pulse, &mut context)?;
+                output.volume = DashiVolume::from(n).clamp(self.limits(Some(&output.name)));
+                self.set_volume(&mut pulse, &mut context, &mut output)?;
+                self.click(&mut pulse, &mut context, &output.name);
+                self.remember(&output);
+                self.feedback_output(&output.name, output.volume, output.muted, false)?;
             }
             VolumeCommand::Get => {
-                let (_, volume, _) = self.get_output(&mut pulse, &mut context)?;
-                notify(self.silent, "Volume", format!("{}%", volume))?;
+                let output = self.get_output(&mut pulse, &mut context)?;
+                self.feedback_output(&output.name, output.volume, output.muted, false)?;
             }
             VolumeCommand::Muted => {
-                let (_, volume, muted) = self.get_output(&mut pulse, &mut context)?;
-                if muted {
-                    notify(self.silent, "Volume", "Muted")?;
-                } else {
-                    notify(self.silent, "Volume", format!("{}%", volume))?;
-                }
+                let output = self.get_output(&mut pulse, &mut context)?;
+                self.feedback_output(&output.name, output.volume, output.muted, true)?;
             }
             VolumeCommand::MutedMic => {
-                if self.get_input(&mut pulse, &mut context)? {
-                    notify(self.silent, "Microphone", "Disabled")?;
-                } else {
-                    notify(self.silent, "Volume", "Enabled")?;
-                }
+                let input = self.get_input(&mut pulse, &mut context)?;
+                self.feedback_input(&input.name, input.volume, input.muted)?;
             }
             VolumeCommand::Mute => {
-                let (_, volume, muted) = self.get_output(&mut pulse, &mut context)?;
-                self.mute_output(&mut pulse, &mut context, !muted)?;
+                let mut output = self.get_output(&mut pulse, &mut context)?;
+                output.muted = !output.muted;
+                self.mute_output(&mut pulse, &mut context, &output)?;
+                self.remember(&output);
+                self.feedback_output(&output.name, output.volume, output.muted, true)?;
+            }
+            VolumeCommand::MuteMic => {
+                let mut input = self.get_input(&mut pulse, &mut context)?;
+                input.muted = !input.muted;
+                self.mute_input(&mut pulse, &mut context, input.muted)?;
+                self.feedback_input(&input.name, input.volume, input.muted)?;
+            }
+            VolumeCommand::Sinks => {
+                let sinks = self.get_sinks(&mut pulse, &mut context)?;
+                let (default, _) = self.get_defaults(&mut pulse, &mut context)?;
+                self.list(&sinks, &default);
+            }
+            VolumeCommand::Sources => {
+                let sources = self.get_sources(&mut pulse, &mut context)?;
+                let (_, default) = self.get_defaults(&mut pulse, &mut context)?;
+                self.list(&sources, &default);
+            }
+            VolumeCommand::Default { sink } => {
+                let sinks = self.get_sinks(&mut pulse, &mut context)?;
+                let sink = Self::find_sink(&sinks, &sink)?;
+                self.switch_sink(&mut pulse, &mut context, sink)?;
+            }
+            VolumeCommand::NextSink => {
+                let (default, _) = self.get_defaults(&mut pulse, &mut context)?;
+                let sinks = self.get_sinks(&mut pulse, &mut context)?;
+                let sink = self.next_sink(&sinks, &default)?;
+                self.switch_sink(&mut pulse, &mut context, sink)?;
+            }
+            VolumeCommand::Apps => {
+                let apps = self.get_sink_inputs(&mut pulse, &mut context)?;
+                self.list_apps(&apps);
+            }
+            VolumeCommand::App { target, modifier } => {
+                let apps = self.get_sink_inputs(&mut pulse, &mut context)?;
+                self.change_apps(apps, &target, &modifier, |app| {
+                    match modifier {
+                        AppCommand::Mute => self.mute_app(&mut pulse, &mut context, app)?,
+                        _ => self.set_app_volume(&mut pulse, &mut context, app)?,
+                    }
+                    Ok(())
+                })?;
+            }
+            VolumeCommand::Ptt { modifier } => {
+                let mut input = self.get_input(&mut pulse, &mut context)?;
+                input.muted = matches!(modifier, PttCommand::Release);
+                self.mute_input(&mut pulse, &mut context, input.muted)?;
+                self.feedback_input(&input.name, input.volume, input.muted)?;
+            }
+            VolumeCommand::Mic { modifier } => {
+                let mut input = self.get_input(&mut pulse, &mut context)?;
+                let limits = self.limits(None);
+                match modifier {
+                    MicCommand::Add { n } => input.volume = input.volume.change(n as i32, limits),
+                    MicCommand::Sub { n } => {
+                        input.volume = input.volume.change(-(n as i32), limits)
+                    }
+                    MicCommand::Set { n } => input.volume = DashiVolume::from(n).clamp(limits),
+                    MicCommand::Get => {}
+                }
 
-                if !muted {
-                    notify(self.silent, "Volume", "Muted")?;
-                } else {
-                    notify(self.silent, "Volume", format!("{}%", volume))?;
+                if !matches!(modifier, MicCommand::Get) {
+                    self.set_input_volume(&mut pulse, &mut context, &mut input)?;
                 }
+
+                self.feedback_input_volume(&input.name, input.volume, input.muted)?;
             }
-            VolumeCommand::MuteMic => {
-                let mic_muted = self.get_input(&mut pulse, &mut context)?;
-                self.mute_input(&mut pulse, &mut context, !mic_muted)?;
+            VolumeCommand::Balance { n } => {
+                let mut output = self.get_output(&mut pulse, &mut context)?;
+                if let Some(n) = n {
+                    output.set_balance(n)?;
+                    self.set_channels(&mut pulse, &mut context, &output)?;
+                }
 
-                if !mic_muted {
-                    notify(self.silent, "Microphone", "Disabled")?;
-                } else {
-                    notify(self.silent, "Microphone", "Enabled")?;
+                self.feedback_balance(&output)?;
+            }
+            VolumeCommand::Watch => self.watch(&mut pulse, &mut context)?,
+            VolumeCommand::Daemon => self.daemon(&mut pulse, &mut context)?,
+            VolumeCommand::Profiles { card } => {
+                let cards = self
+                    .get_cards(&mut pulse, &mut context)?
+                    .into_iter()
+                    .filter(|entry| card.as_ref().is_none_or(|card| entry.matches(card)))
+                    .collect::<Vec<PaCard>>();
+
+                self.list_profiles(&cards);
+            }
+            VolumeCommand::Profile { card, profile } => {
+                let cards = self.get_cards(&mut pulse, &mut context)?;
+                let card = cards
+                    .iter()
+                    .find(|entry| entry.matches(&card))
+                    .ok_or(Error::msg(format!("Could not find card {}", card)))?;
+                let profile = card
+                    .profiles
+                    .iter()
+                    .find(|entry| entry.name == profile)
+                    .ok_or(Error::msg(format!(
+                        "Could not find profile {} of {}",
+                        profile, card.name
+                    )))?;
+
+                self.switch_profile(&mut pulse, &mut context, card, profile)?;
+            }
+            VolumeCommand::ToggleHeadset => {
+                let cards = self.get_cards(&mut pulse, &mut context)?;
+                let card = cards
+                    .iter()
+                    .filter(|card| card.name.starts_with("bluez_card."))
+                    .max_by_key(|card| card.active != "off")
+                    .ok_or(Error::msg("Could not find a Bluetooth card"))?;
+
+                let to_headset = !PaProfile::is_headset(&card.active);
+                let profile = card
+                    .profiles
+                    .iter()
+                    .filter(|profile| {
+                        profile.available
+                            && match to_headset {
+                                true => PaProfile::is_headset(&profile.name),
+                                false => profile.name.starts_with("a2dp"),
+                            }
+                    })
+                    .max_by_key(|profile| profile.priority)
+                    .ok_or(Error::msg(format!(
+                        "Could not find a {} profile of {}",
+                        if to_headset { "headset" } else { "A2DP" },
+                        card.name
+                    )))?;
+
+                self.switch_profile(&mut pulse, &mut context, card, profile)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Limits of the sink, or the global limits for applications and sources
+    fn limits(&self, sink: Option<&str>) -> VolumeLimits {
+        VolumeLimits {
+            max: self.config.max(sink) as i32,
+            step: self.config.step(sink) as i32,
+        }
+    }
+
+    /// Prints the sink state whenever its volume, mute or the default sink changes, and
+    /// keeps the microphone mute LED in sync
+    fn watch(&self, main_loop: &mut Mainloop, context: &mut Context) -> Result<(), Error> {
+        let changed = Rc::new(Cell::new(true));
+        let events = changed.clone();
+
+        context.set_subscribe_callback(Some(Box::new(move |_, _, _| events.set(true))));
+        context.subscribe(
+            InterestMaskSet::SINK | InterestMaskSet::SOURCE | InterestMaskSet::SERVER,
+            |_| {},
+        );
+
+        let mut last = None;
+
+        loop {
+            if changed.replace(false) {
+                if let Ok(output) = self.get_output(main_loop, context) {
+                    self.watch_output(&output, &mut last);
+                }
+
+                if let Ok(input) = self.get_input(main_loop, context) {
+                    self.sync_input_led(input.muted);
+                }
+            }
+
+            match main_loop.iterate(true) {
+                IterateResult::Success(_) => {}
+                IterateResult::Quit(_) => return Err(Error::new(PAErr::from(Code::Killed))),
+                IterateResult::Err(err) => return Err(Error::new(err)),
+            };
+        }
+    }
+
+    /// Prints the sink state if it differs from the last one printed by `watch`
+    fn watch_output(&self, output: &PaDevice, last: &mut Option<(String, i32, bool)>) {
+        let state = (output.name.clone(), output.volume.0, output.muted);
+        if last.as_ref() == Some(&state) {
+            return;
+        }
+
+        self.sync_output_led(output.muted);
+
+        let body = if output.muted {
+            "Muted".to_string()
+        } else {
+            format!("{}%", output.volume)
+        };
+
+        self.format.print(
+            body,
+            json!({ "volume": output.volume.0, "muted": output.muted, "sink": output.name }),
+        );
+        *last = Some(state);
+    }
+
+    /// Applies `modifier` to every application matching `target` and shows the first,
+    /// with `apply` writing the changed mute or channel volumes of each
+    fn change_apps(
+        &self,
+        apps: Vec<PaApp>,
+        target: &str,
+        modifier: &AppCommand,
+        mut apply: impl FnMut(&mut PaApp) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let mut apps = apps
+            .into_iter()
+            .filter(|app| app.matches(target))
+            .collect::<Vec<PaApp>>();
+
+        if apps.is_empty() {
+            return Err(Error::msg(format!("Could not find application {}", target)));
+        }
+
+        let muted = !apps[0].muted;
+        let limits = self.limits(None);
+
+        for app in apps.iter_mut() {
+            match modifier {
+                AppCommand::Add { n } => app.volume = app.volume.change(*n as i32, limits),
+                AppCommand::Sub { n } => app.volume = app.volume.change(-(*n as i32), limits),
+                AppCommand::Set { n } => app.volume = DashiVolume::from(*n).clamp(limits),
+                AppCommand::Mute => app.muted = muted,
+            }
+
+            apply(app)?;
+        }
+
+        self.feedback_app(&apps[0], matches!(modifier, AppCommand::Mute))
+    }
+
+    /// Switches to new sinks that rank at least as high as the default sink, and away
+    /// from the default sink when it disappears, preferring recently used sinks.
+    /// Volume changes of the default sink are remembered and restored when it returns
+    fn daemon(&self, main_loop: &mut Mainloop, context: &mut Context) -> Result<(), Error> {
+        if Daemon::new("dashi-volume")?.is_running()? {
+            return Err(Error::msg("Dashi volume daemon is already in use"));
+        }
+
+        println!("Dashi volume daemon started");
+
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let queue = events.clone();
+
+        context.set_subscribe_callback(Some(Box::new(move |facility, operation, index| {
+            if let (Some(facility), Some(operation)) = (facility, operation) {
+                queue.borrow_mut().push((facility, operation, index));
+            }
+        })));
+        context.subscribe(
+            InterestMaskSet::SINK
+                | InterestMaskSet::SOURCE
+                | InterestMaskSet::SERVER
+                | InterestMaskSet::SOURCE_OUTPUT,
+            |_| {},
+        );
+
+        let (default, _) = self.get_defaults(main_loop, context)?;
+        let mut state = DaemonState {
+            sinks: self.get_sinks(main_loop, context)?,
+            history: vec![default.clone()],
+            default,
+            auto_muted: false,
+        };
+
+        if self.config.auto_mute_mic {
+            self.auto_mute_mic(main_loop, context, &mut state.auto_muted)?;
+        }
+
+        loop {
+            match main_loop.iterate(true) {
+                IterateResult::Success(_) => {}
+                IterateResult::Quit(_) => return Err(Error::new(PAErr::from(Code::Killed))),
+                IterateResult::Err(err) => return Err(Error::new(err)),
+            };
+
+            let pending = std::mem::take(&mut *events.borrow_mut());
+            if pending.is_empty() {
+                continue;
+            }
+
+            // Devices and streams come and go between events, which must not stop the daemon
+            if let Err(err) = self.handle_events(main_loop, context, &pending, &mut state) {
+                eprintln!("Dashi volume daemon: {}", err);
+            }
+        }
+    }
+
+    fn handle_events(
+        &self,
+        main_loop: &mut Mainloop,
+        context: &mut Context,
+        pending: &[(Facility, Operation, u32)],
+        state: &mut DaemonState,
+    ) -> Result<(), Error> {
+        let previous = std::mem::replace(&mut state.sinks, self.get_sinks(main_loop, context)?);
+        let previous_default =
+            std::mem::replace(&mut state.default, self.get_defaults(main_loop, context)?.0);
+
+        for &(facility, operation, index) in pending {
+            if facility != Facility::Sink {
+                continue;
+            }
+
+            let target = match operation {
+                Operation::New => state.sinks.iter().find(|sink| {
+                    sink.index == index
+                        && sink.available
+                        && sink.name != state.default
+                        && self.rank(&sink.name) <= self.rank(&state.default)
+                }),
+                Operation::Removed
+                    if previous
+                        .iter()
+                        .any(|sink| sink.index == index && sink.name == previous_default) =>
+                {
+                    state
+                        .sinks
+                        .iter()
+                        .filter(|sink| sink.available)
+                        .min_by_key(|sink| {
+                            (
+                                self.rank(&sink.name),
+                                Reverse(state.history.iter().position(|name| *name == sink.name)),
+                            )
+                        })
+                        .filter(|sink| sink.name != state.default)
+                }
+                _ => None,
+            };
+
+            if let Some(sink) = target {
+                match self.switch_sink(main_loop, context, sink) {
+                    Ok(()) => state.default = sink.name.clone(),
+                    Err(err) => eprintln!("Could not switch to {}: {}", sink.name, err),
                 }
             }
         }
 
+        if let Err(err) = self.restore(main_loop, context) {
+            eprintln!("Could not restore the sink volume: {}", err);
+        }
+
+        if self.config.auto_mute_mic
+            && pending.iter().any(|&(facility, _, _)| {
+                facility == Facility::SourceOutput || facility == Facility::Server
+            })
+            && let Err(err) = self.auto_mute_mic(main_loop, context, &mut state.auto_muted)
+        {
+            eprintln!("Could not mute the microphone: {}", err);
+        }
+
+        let changed = pending.iter().any(|&(facility, operation, index)| {
+            facility == Facility::Sink
+                && operation == Operation::Changed
+                && state
+                    .sinks
+                    .iter()
+                    .any(|sink| sink.index == index && sink.name == state.default)
+        });
+
+        // The sink or source may already be gone again
+        if changed && let Ok(output) = self.get_sink(main_loop, context, &state.default) {
+            sync_led(MUTE_LED, output.muted);
+            self.remember(&output);
+        }
+
+        if pending
+            .iter()
+            .any(|&(facility, _, _)| facility == Facility::Source)
+            && let Ok(input) = self.get_input(main_loop, context)
+        {
+            self.sync_input_led(input.muted);
+        }
+
+        state.history.retain(|name| *name != state.default);
+        state.history.push(state.default.clone());
+
+        Ok(())
+    }
+
+    /// Mutes the source while nothing records from it, and unmutes it for the next
+    /// recording only when it was muted here
+    fn auto_mute_mic(
+        &self,
+        main_loop: &mut Mainloop,
+        context: &mut Context,
+        auto_muted: &mut bool,
+    ) -> Result<(), Error> {
+        let input = self.get_input(main_loop, context)?;
+        let recording = self
+            .get_source_outputs(main_loop, context)?
+            .contains(&input.index);
+
+        if !recording && !input.muted {
+            self.mute_input(main_loop, context, true)?;
+            self.sync_input_led(true);
+            notify(self.silent, "Microphone", "Disabled")?;
+            *auto_muted = true;
+        } else if recording && input.muted && *auto_muted {
+            self.mute_input(main_loop, context, false)?;
+            self.sync_input_led(false);
+            notify(self.silent, "Microphone", "Enabled")?;
+            *auto_muted = false;
+        }
+
+        Ok(())
+    }
+
+    fn remember(&self, output: &PaDevice) {
+        let result = Memory::new().and_then(|mut memory| {
+            memory.remember(&output.name, output.volume.0, output.muted);
+            memory.save()
+        });
+
+        // The volume has already changed, so the command still shows it
+        if let Err(err) = result {
+            eprintln!("Could not remember the sink volume: {}", err);
+        }
+    }
+
+    /// Reapplies the remembered volume and mute once a sink becomes the default
+    fn restore(&self, main_loop: &mut Mainloop, context: &mut Context) -> Result<(), Error> {
+        let (default, _) = self.get_defaults(main_loop, context)?;
+        let mut memory = Memory::new()?;
+
+        if memory.default == default {
+            return Ok(());
+        }
+
+        if let Some(level) = memory.level(&default) {
+            let mut output = self.get_sink(main_loop, context, &default)?;
+            output.volume = DashiVolume(level.volume);
+            output.muted = level.muted;
+
+            self.set_volume(main_loop, context, &mut output)?;
+            self.mute_output(main_loop, context, &output)?;
+            sync_led(MUTE_LED, output.muted);
+        }
+
+        memory.default = default;
+        memory.save()
+    }
+
+    /// Position of the sink in the configured priority, where unlisted sinks rank last
+    fn rank(&self, sink: &str) -> usize {
+        self.config
+            .priority
+            .iter()
+            .position(|prefix| sink.starts_with(prefix.as_str()))
+            .unwrap_or(self.config.priority.len())
+    }
+
+    fn find_sink<'a>(sinks: &'a [PaEntry], sink: &str) -> Result<&'a PaEntry, Error> {
+        sinks
+            .iter()
+            .find(|entry| entry.name == sink || entry.index.to_string() == sink)
+            .ok_or(Error::msg(format!("Could not find sink {}", sink)))
+    }
+
+    /// Available sink after the default one, restricted to the configured cycle
+    fn next_sink<'a>(&self, sinks: &'a [PaEntry], default: &str) -> Result<&'a PaEntry, Error> {
+        let sinks = sinks
+            .iter()
+            .filter(|sink| {
+                sink.available
+                    && (self.config.cycle.is_empty() || self.config.cycle.contains(&sink.name))
+            })
+            .collect::<Vec<&PaEntry>>();
+
+        let next = sinks
+            .iter()
+            .position(|sink| sink.name == default)
+            .map(|current| (current + 1) % sinks.len())
+            .unwrap_or(0);
+
+        sinks
+            .get(next)
+            .copied()
+            .ok_or(Error::msg("Could not find an available sink"))
+    }
+
+    /// Makes the sink default and moves every playing stream onto it
+    fn switch_sink(
+        &self,
+        main_loop: &mut Mainloop,
+        context: &mut Context,
+        sink: &PaEntry,
+    ) -> Result<(), Error> {
+        self.set_default_sink(main_loop, context, &sink.name)?;
+
+        // Streams may end before they are moved or refuse to move, which leaves the rest
+        for input in self.get_sink_inputs(main_loop, context)? {
+            if let Err(err) = self.move_sink_input(main_loop, context, input.index, sink.index) {
+                eprintln!("Could not move {} to {}: {}", input.name, sink.name, err);
+            }
+        }
+
+        if let Err(err) = self.restore(main_loop, context) {
+            eprintln!("Could not restore the sink volume: {}", err);
+        }
+
+        self.feedback_sink(sink)
+    }
+
+    fn switch_profile(
+        &self,
+        main_loop: &mut Mainloop,
+        context: &mut Context,
+        card: &PaCard,
+        profile: &PaProfile,
+    ) -> Result<(), Error> {
+        self.set_card_profile(main_loop, context, card.index, &profile.name)?;
+
+        notify(self.silent, &card.description, &profile.description)?;
+        self.format.print(
+            &profile.description,
+            json!({ "card": card.name, "profile": profile.name }),
+        );
+
+        Ok(())
+    }
+
+    fn feedback_sink(&self, sink: &PaEntry) -> Result<(), Error> {
+        notify(self.silent, "Audio Output", &sink.description)?;
+        self.format.print(
+            &sink.description,
+            json!({ "sink": sink.name, "description": sink.description }),
+        );
+
+        Ok(())
+    }
+
+    fn list(&self, entries: &[PaEntry], default: &str) {
+        let plain = entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    "{}{}: {} ({}) [{}]",
+                    if entry.name == default { "* " } else { "  " },
+                    entry.index,
+                    entry.description,
+                    entry.name,
+                    entry.state
+                )
+            })
+            .collect::<Vec<String>>();
+
+        let json = entries
+            .iter()
+            .map(|entry| {
+                json!({
+                    "index": entry.index,
+                    "name": entry.name,
+                    "description": entry.description,
+                    "state": entry.state,
+                    "default": entry.name == default,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        self.format.print(plain.join("\n"), json!(json));
+    }
+
+    fn list_profiles(&self, cards: &[PaCard]) {
+        let mut plain = Vec::new();
+        let mut json = Vec::new();
+
+        for card in cards {
+            plain.push(format!(
+                "{}: {} ({})",
+                card.index, card.description, card.name
+            ));
+
+            for profile in &card.profiles {
+                let active = if profile.name == card.active {
+                    "* "
+                } else {
+                    "  "
+                };
+                let available = if profile.available {
+                    ""
+                } else {
+                    " [unavailable]"
+                };
+                plain.push(format!(
+                    "  {}{}: {}{}",
+                    active, profile.name, profile.description, available
+                ));
+            }
+
+            let profiles = card
+                .profiles
+                .iter()
+                .map(|profile| {
+                    json!({
+                        "name": profile.name,
+                        "description": profile.description,
+                        "priority": profile.priority,
+                        "available": profile.available,
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            json.push(json!({
+                "index": card.index,
+                "name": card.name,
+                "description": card.description,
+                "active": card.active,
+                "profiles": profiles,
+            }));
+        }
+
+        self.format.print(plain.join("\n"), json!(json));
+    }
+
+    fn list_apps(&self, apps: &[PaApp]) {
+        let plain = apps
+            .iter()
+            .map(|app| {
+                format!(
+                    "{}: {} ({}, pid {}) {}%{}",
+                    app.index,
+                    app.name,
+                    app.binary,
+                    app.pid
+                        .map(|pid| pid.to_string())
+                        .unwrap_or("?".to_string()),
+                    app.volume,
+                    if app.muted { " [muted]" } else { "" }
+                )
+            })
+            .collect::<Vec<String>>();
+
+        let json = apps
+            .iter()
+            .map(|app| {
+                json!({
+                    "index": app.index,
+                    "name": app.name,
+                    "binary": app.binary,
+                    "pid": app.pid,
+                    "volume": app.volume.0,
+                    "muted": app.muted,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        self.format.print(plain.join("\n"), json!(json));
+    }
+
+    fn feedback_app(&self, app: &PaApp, show_muted: bool) -> Result<(), Error> {
+        let body = if show_muted && app.muted {
+            "Muted".to_string()
+        } else {
+            format!("{}%", app.volume)
+        };
+
+        notify(self.silent, &app.name, &body)?;
+        self.format.print(
+            format!("{}: {}", app.name, body),
+            json!({ "volume": app.volume.0, "muted": app.muted, "app": app.name }),
+        );
+
+        Ok(())
+    }
+
+    fn feedback_output(
+        &self,
+        sink: &str,
+        volume: DashiVolume,
+        muted: bool,
+        show_muted: bool,
+    ) -> Result<(), Error> {
+        let body = if show_muted && muted {
+            "Muted".to_string()
+        } else {
+            format!("{}%", volume)
+        };
+
+        self.sync_output_led(muted);
+        notify(self.silent, "Volume", &body)?;
+        self.format.print(
+            body,
+            json!({ "volume": volume.0, "muted": muted, "sink": sink }),
+        );
+
+        Ok(())
+    }
+
+    /// LEDs only follow the default sink, as they are not tied to one device
+    fn sync_output_led(&self, muted: bool) {
+        if self.sink == DEFAULT_SINK {
+            sync_led(MUTE_LED, muted);
+        }
+    }
+
+    fn sync_input_led(&self, muted: bool) {
+        if self.source == DEFAULT_SOURCE {
+            sync_led(MICMUTE_LED, muted);
+        }
+    }
+
+    fn feedback_balance(&self, output: &PaDevice) -> Result<(), Error> {
+        let balance = (output.channels.get_balance(&output.channel_map) * 100.0).round() as i32;
+        let body = match balance {
+            0 => "Centered".to_string(),
+            ..0 => format!("Left {}%", -balance),
+            _ => format!("Right {}%", balance),
+        };
+
+        notify(self.silent, "Balance", &body)?;
+        self.format
+            .print(balance, json!({ "balance": balance, "sink": output.name }));
+
+        Ok(())
+    }
+
+    fn feedback_input(&self, source: &str, volume: DashiVolume, muted: bool) -> Result<(), Error> {
+        let body = if muted { "Disabled" } else { "Enabled" };
+
+        self.sync_input_led(muted);
+        notify(self.silent, "Microphone", body)?;
+        self.format.print(
+            body,
+            json!({ "volume": volume.0, "muted": muted, "source": source }),
+        );
+
+        Ok(())
+    }
+
+    fn feedback_input_volume(
+        &self,
+        source: &str,
+        volume: DashiVolume,
+        muted: bool,
+    ) -> Result<(), Error> {
+        let body = format!("{}%", volume);
+
+        self.sync_input_led(muted);
+        notify(self.silent, "Microphone", &body)?;
+        self.format.print(
+            body,
+            json!({ "volume": volume.0, "muted": muted, "source": source }),
+        );
+
         Ok(())
     }
 
@@ -122,16 +986,32 @@ impl VolumeSpec {
         &self,
         main_loop: &mut Mainloop,
         context: &mut Context,
-    ) -> Result<(ChannelVolumes, DashiVolume, bool), PAErr> {
-        let cmd_get_volume = PaFeedback::<(ChannelVolumes, DashiVolume, bool)>::new();
+    ) -> Result<PaDevice, PAErr> {
+        self.get_sink(main_loop, context, &self.sink)
+    }
+
+    fn get_sink(
+        &self,
+        main_loop: &mut Mainloop,
+        context: &mut Context,
+        name: &str,
+    ) -> Result<PaDevice, PAErr> {
+        let cmd_get_volume = PaFeedback::<PaDevice>::new();
         cmd_get_volume.run(main_loop, |result| {
             context
                 .introspect()
-                .get_sink_info_by_name("@DEFAULT_SINK@", move |info| {
+                .get_sink_info_by_name(name, move |info| {
                     let mut result = result.lock().unwrap();
                     match info {
                         ListResult::Item(info) => {
-                            *result = Some(Ok((info.volume, info.volume.max().into(), info.mute)))
+                            *result = Some(Ok(PaDevice {
+                                index: info.index,
+                                name: info.name.as_deref().unwrap_or_default().to_string(),
+                                channels: info.volume,
+                                channel_map: info.channel_map,
+                                volume: info.volume.max().into(),
+                                muted: info.mute,
+                            }))
                         }
                         ListResult::Error => *result = Some(Err(PAErr::from(Code::Internal))),
                         ListResult::End => {}
@@ -140,15 +1020,28 @@ impl VolumeSpec {
         })
     }
 
-    fn get_input(&self, main_loop: &mut Mainloop, context: &mut Context) -> Result<bool, PAErr> {
-        let cmd_get_volume = PaFeedback::<bool>::new();
+    fn get_input(
+        &self,
+        main_loop: &mut Mainloop,
+        context: &mut Context,
+    ) -> Result<PaDevice, PAErr> {
+        let cmd_get_volume = PaFeedback::<PaDevice>::new();
         cmd_get_volume.run(main_loop, |result| {
             context
                 .introspect()
-                .get_source_info_by_name("@DEFAULT_SOURCE@", move |info| {
+                .get_source_info_by_name(&self.source, move |info| {
                     let mut result = result.lock().unwrap();
                     match info {
-                        ListResult::Item(info) => *result = Some(Ok(info.mute)),
+                        ListResult::Item(info) => {
+                            *result = Some(Ok(PaDevice {
+                                index: info.index,
+                                name: info.name.as_deref().unwrap_or_default().to_string(),
+                                channels: info.volume,
+                                channel_map: info.channel_map,
+                                volume: info.volume.max().into(),
+                                muted: info.mute,
+                            }))
+                        }
                         ListResult::Error => *result = Some(Err(PAErr::from(Code::Internal))),
                         ListResult::End => {}
                     };
@@ -160,15 +1053,46 @@ impl VolumeSpec {
         &self,
         main_loop: &mut Mainloop,
         context: &mut Context,
-        channels: &mut ChannelVolumes,
-        volume: DashiVolume,
+        output: &mut PaDevice,
     ) -> Result<(), PAErr> {
-        volume.set(channels);
-        let cmd_get_volume = PaFeedback::<()>::new();
-        cmd_get_volume.run(main_loop, |result| {
+        output.volume.set(&mut output.channels);
+        self.set_channels(main_loop, context, output)
+    }
+
+    fn set_channels(
+        &self,
+        main_loop: &mut Mainloop,
+        context: &mut Context,
+        output: &PaDevice,
+    ) -> Result<(), PAErr> {
+        let cmd_set_volume = PaFeedback::<()>::new();
+        cmd_set_volume.run(main_loop, |result| {
             context.introspect().set_sink_volume_by_name(
-                "@DEFAULT_SINK@",
-                channels,
+                &output.name,
+                &output.channels,
+                Some(Box::new(move |success| {
+                    let mut result = result.lock().unwrap();
+                    match success {
+                        true => *result = Some(Ok(())),
+                        false => *result = Some(Err(PAErr::from(Code::Internal))),
+                    }
+                })),
+            );
+        })
+    }
+
+    fn set_input_volume(
+        &self,
+        main_loop: &mut Mainloop,
+        context: &mut Context,
+        input: &mut PaDevice,
+    ) -> Result<(), PAErr> {
+        input.volume.set(&mut input.channels);
+        let cmd_set_volume = PaFeedback::<()>::new();
+        cmd_set_volume.run(main_loop, |result| {
+            context.introspect().set_source_volume_by_name(
+                &self.source,
+                &input.channels,
                 Some(Box::new(move |success| {
                     let mut result = result.lock().unwrap();
                     match success {
@@ -184,13 +1108,13 @@ impl VolumeSpec {
         &self,
         main_loop: &mut Mainloop,
         context: &mut Context,
-        muted: bool,
+        output: &PaDevice,
     ) -> Result<(), PAErr> {
         let cmd_get_volume = PaFeedback::<()>::new();
         cmd_get_volume.run(main_loop, |result| {
             context.introspect().set_sink_mute_by_name(
-                "@DEFAULT_SINK@",
-                muted,
+                &output.name,
+                output.muted,
                 Some(Box::new(move |success| {
                     let mut result = result.lock().unwrap();
                     match success {
@@ -211,7 +1135,7 @@ impl VolumeSpec {
         let cmd_get_volume = PaFeedback::<()>::new();
         cmd_get_volume.run(main_loop, |result| {
             context.introspect().set_source_mute_by_name(
-                "@DEFAULT_SOURCE@",
+                &self.source,
                 muted,
                 Some(Box::new(move |success| {
                     let mut result = result.lock().unwrap();
@@ -223,6 +1147,328 @@ impl VolumeSpec {
             );
         })
     }
+
+    fn get_sinks(
+        &self,
+        main_loop: &mut Mainloop,
+        context: &mut Context,
+    ) -> Result<Vec<PaEntry>, PAErr> {
+        let cmd_get_sinks = PaFeedback::<Vec<PaEntry>>::new();
+        cmd_get_sinks.run(main_loop, |result| {
+            let mut sinks = Vec::new();
+            context.introspect().get_sink_info_list(move |info| {
+                let mut result = result.lock().unwrap();
+                match info {
+                    ListResult::Item(info) => sinks.push(PaEntry {
+                        index: info.index,
+                        name: info.name.as_deref().unwrap_or_default().to_string(),
+                        description: info.description.as_deref().unwrap_or_default().to_string(),
+                        state: format!("{:?}", info.state).to_lowercase(),
+                        available: info
+                            .active_port
+                            .as_ref()
+                            .is_none_or(|port| port.available != PortAvailable::No),
+                    }),
+                    ListResult::Error => *result = Some(Err(PAErr::from(Code::Internal))),
+                    ListResult::End => *result = Some(Ok(std::mem::take(&mut sinks))),
+                };
+            });
+        })
+    }
+
+    fn get_sources(
+        &self,
+        main_loop: &mut Mainloop,
+        context: &mut Context,
+    ) -> Result<Vec<PaEntry>, PAErr> {
+        let cmd_get_sources = PaFeedback::<Vec<PaEntry>>::new();
+        cmd_get_sources.run(main_loop, |result| {
+            let mut sources = Vec::new();
+            context.introspect().get_source_info_list(move |info| {
+                let mut result = result.lock().unwrap();
+                match info {
+                    ListResult::Item(info) => sources.push(PaEntry {
+                        index: info.index,
+                        name: info.name.as_deref().unwrap_or_default().to_string(),
+                        description: info.description.as_deref().unwrap_or_default().to_string(),
+                        state: format!("{:?}", info.state).to_lowercase(),
+                        available: info
+                            .active_port
+                            .as_ref()
+                            .is_none_or(|port| port.available != PortAvailable::No),
+                    }),
+                    ListResult::Error => *result = Some(Err(PAErr::from(Code::Internal))),
+                    ListResult::End => *result = Some(Ok(std::mem::take(&mut sources))),
+                };
+            });
+        })
+    }
+
+    /// Names of the default sink and source
+    fn get_defaults(
+        &self,
+        main_loop: &mut Mainloop,
+        context: &mut Context,
+    ) -> Result<(String, String), PAErr> {
+        let cmd_get_defaults = PaFeedback::<(String, String)>::new();
+        cmd_get_defaults.run(main_loop, |result| {
+            context.introspect().get_server_info(move |info| {
+                *result.lock().unwrap() = Some(Ok((
+                    info.default_sink_name
+                        .as_deref()
+                        .unwrap_or_default()
+                        .to_string(),
+                    info.default_source_name
+                        .as_deref()
+                        .unwrap_or_default()
+                        .to_string(),
+                )));
+            });
+        })
+    }
+
+    fn set_default_sink(
+        &self,
+        main_loop: &mut Mainloop,
+        context: &mut Context,
+        name: &str,
+    ) -> Result<(), PAErr> {
+        let cmd_set_default = PaFeedback::<()>::new();
+        cmd_set_default.run(main_loop, |result| {
+            context.set_default_sink(name, move |success| {
+                let mut result = result.lock().unwrap();
+                match success {
+                    true => *result = Some(Ok(())),
+                    false => *result = Some(Err(PAErr::from(Code::Internal))),
+                }
+            });
+        })
+    }
+
+    fn get_sink_inputs(
+        &self,
+        main_loop: &mut Mainloop,
+        context: &mut Context,
+    ) -> Result<Vec<PaApp>, PAErr> {
+        let cmd_get_inputs = PaFeedback::<Vec<PaApp>>::new();
+        cmd_get_inputs.run(main_loop, |result| {
+            let mut inputs = Vec::new();
+            context.introspect().get_sink_input_info_list(move |info| {
+                let mut result = result.lock().unwrap();
+                match info {
+                    ListResult::Item(info) => {
+                        let property = |key| info.proplist.get_str(key).unwrap_or_default();
+                        inputs.push(PaApp {
+                            index: info.index,
+                            name: property(properties::APPLICATION_NAME),
+                            binary: property(properties::APPLICATION_PROCESS_BINARY),
+                            pid: property(properties::APPLICATION_PROCESS_ID).parse().ok(),
+                            channels: info.volume,
+                            volume: info.volume.max().into(),
+                            muted: info.mute,
+                        })
+                    }
+                    ListResult::Error => *result = Some(Err(PAErr::from(Code::Internal))),
+                    ListResult::End => *result = Some(Ok(std::mem::take(&mut inputs))),
+                };
+            });
+        })
+    }
+
+    fn set_app_volume(
+        &self,
+        main_loop: &mut Mainloop,
+        context: &mut Context,
+        app: &mut PaApp,
+    ) -> Result<(), PAErr> {
+        app.volume.set(&mut app.channels);
+        let cmd_set_volume = PaFeedback::<()>::new();
+        cmd_set_volume.run(main_loop, |result| {
+            context.introspect().set_sink_input_volume(
+                app.index,
+                &app.channels,
+                Some(Box::new(move |success| {
+                    let mut result = result.lock().unwrap();
+                    match success {
+                        true => *result = Some(Ok(())),
+                        false => *result = Some(Err(PAErr::from(Code::Internal))),
+                    }
+                })),
+            );
+        })
+    }
+
+    fn mute_app(
+        &self,
+        main_loop: &mut Mainloop,
+        context: &mut Context,
+        app: &PaApp,
+    ) -> Result<(), PAErr> {
+        let cmd_mute = PaFeedback::<()>::new();
+        cmd_mute.run(main_loop, |result| {
+            context.introspect().set_sink_input_mute(
+                app.index,
+                app.muted,
+                Some(Box::new(move |success| {
+                    let mut result = result.lock().unwrap();
+                    match success {
+                        true => *result = Some(Ok(())),
+                        false => *result = Some(Err(PAErr::from(Code::Internal))),
+                    }
+                })),
+            );
+        })
+    }
+
+    /// Source indices of every source output
+    fn get_source_outputs(
+        &self,
+        main_loop: &mut Mainloop,
+        context: &mut Context,
+    ) -> Result<Vec<u32>, PAErr> {
+        let cmd_get_outputs = PaFeedback::<Vec<u32>>::new();
+        cmd_get_outputs.run(main_loop, |result| {
+            let mut outputs = Vec::new();
+            context
+                .introspect()
+                .get_source_output_info_list(move |info| {
+                    let mut result = result.lock().unwrap();
+                    match info {
+                        ListResult::Item(info) => outputs.push(info.source),
+                        ListResult::Error => *result = Some(Err(PAErr::from(Code::Internal))),
+                        ListResult::End => *result = Some(Ok(std::mem::take(&mut outputs))),
+                    };
+                });
+        })
+    }
+
+    fn move_sink_input(
+        &self,
+        main_loop: &mut Mainloop,
+        context: &mut Context,
+        input: u32,
+        sink: u32,
+    ) -> Result<(), PAErr> {
+        let cmd_move_input = PaFeedback::<()>::new();
+        cmd_move_input.run(main_loop, |result| {
+            context.introspect().move_sink_input_by_index(
+                input,
+                sink,
+                Some(Box::new(move |success| {
+                    let mut result = result.lock().unwrap();
+                    match success {
+                        true => *result = Some(Ok(())),
+                        false => *result = Some(Err(PAErr::from(Code::Internal))),
+                    }
+                })),
+            );
+        })
+    }
+
+    fn get_cards(
+        &self,
+        main_loop: &mut Mainloop,
+        context: &mut Context,
+    ) -> Result<Vec<PaCard>, PAErr> {
+        let cmd_get_cards = PaFeedback::<Vec<PaCard>>::new();
+        cmd_get_cards.run(main_loop, |result| {
+            let mut cards = Vec::new();
+            context.introspect().get_card_info_list(move |info| {
+                let mut result = result.lock().unwrap();
+                match info {
+                    ListResult::Item(info) => cards.push(PaCard {
+                        index: info.index,
+                        name: info.name.as_deref().unwrap_or_default().to_string(),
+                        description: info
+                            .proplist
+                            .get_str(properties::DEVICE_DESCRIPTION)
+                            .unwrap_or_default(),
+                        profiles: info
+                            .profiles
+                            .iter()
+                            .map(|profile| PaProfile {
+                                name: profile.name.as_deref().unwrap_or_default().to_string(),
+                                description: profile
+                                    .description
+                                    .as_deref()
+                                    .unwrap_or_default()
+                                    .to_string(),
+                                priority: profile.priority,
+                                available: profile.available,
+                            })
+                            .collect(),
+                        active: info
+                            .active_profile
+                            .as_ref()
+                            .and_then(|profile| profile.name.as_deref())
+                            .unwrap_or_default()
+                            .to_string(),
+                    }),
+                    ListResult::Error => *result = Some(Err(PAErr::from(Code::Internal))),
+                    ListResult::End => *result = Some(Ok(std::mem::take(&mut cards))),
+                };
+            });
+        })
+    }
+
+    fn set_card_profile(
+        &self,
+        main_loop: &mut Mainloop,
+        context: &mut Context,
+        card: u32,
+        profile: &str,
+    ) -> Result<(), PAErr> {
+        let cmd_set_profile = PaFeedback::<()>::new();
+        cmd_set_profile.run(main_loop, |result| {
+            context.introspect().set_card_profile_by_index(
+                card,
+                profile,
+                Some(Box::new(move |success| {
+                    let mut result = result.lock().unwrap();
+                    match success {
+                        true => *result = Some(Ok(())),
+                        false => *result = Some(Err(PAErr::from(Code::Internal))),
+                    }
+                })),
+            );
+        })
+    }
+}
+
+/// Lights `*::mute` or `*::micmute` LEDs while muted so laptop key indicators follow
+/// mute changes from any application. Devices without these LEDs are left alone, as are
+/// LEDs already driven by the kernel `audio-mute` or `audio-micmute` triggers, since
+/// writing their brightness would remove the trigger
+fn sync_led(suffix: &str, muted: bool) {
+    let Ok(devices) = Device::list("leds") else {
+        return;
+    };
+
+    let mut backend = None;
+
+    for device in devices
+        .iter()
+        .filter(|device| device.name().ends_with(suffix))
+        .filter(|device| {
+            !device.read("trigger").is_ok_and(|triggers| {
+                triggers.contains("[audio-mute]") || triggers.contains("[audio-micmute]")
+            })
+        })
+    {
+        let value = match muted {
+            true => device.max_brightness().unwrap_or(1),
+            false => 0,
+        };
+
+        if device
+            .brightness()
+            .is_ok_and(|brightness| brightness != value)
+        {
+            let _ = backend
+                .get_or_insert_with(Backend::new)
+                .set_brightness(device, value);
+        }
+    }
 }
 
 impl<T> PaFeedback<T> {
@@ -251,32 +1497,67 @@ impl<T> PaFeedback<T> {
     }
 }
 
-impl DashiVolume {
-    fn set(&self, channels: &mut ChannelVolumes) {
-        channels
-            .get_mut()
-            .iter_mut()
-            .for_each(|channel| *channel = self.into());
+impl PaCard {
+    fn matches(&self, target: &str) -> bool {
+        self.name == target || self.index.to_string() == target
     }
+}
 
-    fn clamp(self) -> DashiVolume {
-        DashiVolume(self.0.max(0).min(MAX_VOLUME))
+impl PaProfile {
+    /// HSP/HFP profiles, named `headset_head_unit` or `handsfree_head_unit` by PulseAudio
+    /// and `headset-head-unit` by PipeWire
+    fn is_headset(name: &str) -> bool {
+        name.contains("head_unit") || name.contains("head-unit")
     }
 }
 
-impl Add for DashiVolume {
-    type Output = DashiVolume;
+impl PaDevice {
+    /// Balance from -100 (left) to 100 (right), keeping the loudest channel
+    fn set_balance(&mut self, balance: i32) -> Result<(), Error> {
+        self.channels
+            .set_balance(&self.channel_map, balance as f32 / 100.0)
+            .ok_or(Error::msg(format!("Sink {} cannot be balanced", self.name)))?;
 
-    fn add(self, rhs: Self) -> Self::Output {
-        Self(self.0 + rhs.0).clamp()
+        Ok(())
     }
 }
 
-impl Sub for DashiVolume {
-    type Output = DashiVolume;
+impl PaApp {
+    /// Matches the stream index, application name or binary, ignoring case
+    fn matches(&self, target: &str) -> bool {
+        self.index.to_string() == target
+            || self.name.eq_ignore_ascii_case(target)
+            || self.binary.eq_ignore_ascii_case(target)
+    }
+}
+
+impl DashiVolume {
+    /// Scales every channel proportionally so the loudest one matches, which keeps the balance
+    fn set(&self, channels: &mut ChannelVolumes) {
+        channels.scale(self.into());
+    }
+
+    /// Rounds to the nearest step, applies the change and rounds again in its
+    /// direction, so changes smaller than a step still move the volume
+    fn change(self, delta: i32, limits: VolumeLimits) -> DashiVolume {
+        // Volumes raised above the ceiling elsewhere are not lowered by adding
+        if delta > 0 && self.0 >= limits.max {
+            return self;
+        }
+
+        let current = (self.0 as f32 / limits.step as f32).round() as i32 * limits.step;
+        let requested = current + delta;
+        let snapped = if delta > 0 {
+            (requested + limits.step - 1).div_euclid(limits.step) * limits.step
+        } else {
+            requested.div_euclid(limits.step) * limits.step
+        };
+
+        DashiVolume(snapped).clamp(limits)
+    }
 
-    fn sub(self, rhs: Self) -> Self::Output {
-        Self(self.0 - rhs.0).clamp()
+    fn clamp(self, limits: VolumeLimits) -> DashiVolume {
+        DashiVolume(self.0.clamp(0, limits.max))
     }
 }
 
@@ -289,9 +1570,7 @@ impl From<u32> for DashiVolume {
 impl From<PaVolume> for DashiVolume {
     fn from(volume: PaVolume) -> Self {
         let range = PaVolume::NORMAL.0 as f32 - PaVolume::MUTED.0 as f32;
-        let unrounded_percent =
-            ((volume.0 as f32 - PaVolume::MUTED.0 as f32) * 100.0 / range) as i32;
-        DashiVolume((unrounded_percent + 2) / 5 * 5 as i32).clamp()
+        DashiVolume(((volume.0 as f32 - PaVolume::MUTED.0 as f32) * 100.0 / range).round() as i32)
     }
 }
 
@@ -307,3 +1586,45 @@ impl Display for DashiVolume {
         f.write_str(&self.0.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: VolumeLimits = VolumeLimits { max: 150, step: 5 };
+
+    fn change(volume: i32, delta: i32) -> i32 {
+        DashiVolume(volume).change(delta, LIMITS).0
+    }
+
+    #[test]
+    fn change_moves_by_steps() {
+        assert_eq!(change(50, 5), 55);
+        assert_eq!(change(50, -5), 45);
+        assert_eq!(change(50, 12), 65);
+        assert_eq!(change(50, -12), 35);
+    }
+
+    #[test]
+    fn change_smaller_than_step_still_moves() {
+        assert_eq!(change(50, 1), 55);
+        assert_eq!(change(50, -1), 45);
+        assert_eq!(change(52, 1), 55);
+        assert_eq!(change(53, -1), 50);
+    }
+
+    #[test]
+    fn change_stays_within_limits() {
+        assert_eq!(change(3, -5), 0);
+        assert_eq!(change(0, -5), 0);
+        assert_eq!(change(148, 5), 150);
+        assert_eq!(change(150, 5), 150);
+    }
+
+    #[test]
+    fn change_above_max() {
+        assert_eq!(change(160, 5), 160);
+        assert_eq!(change(160, -5), 150);
+        assert_eq!(change(200, -60), 140);
+    }
+}
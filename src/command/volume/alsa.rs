@@ -0,0 +1,145 @@
+use std::process::Command;
+
+use color_eyre::{eyre::Error, Result};
+
+use crate::command::{MicCommand, PttCommand, VolumeCommand};
+
+use super::{DashiVolume, VolumeLimits, VolumeSpec, DEFAULT_SINK, DEFAULT_SOURCE};
+
+// Simple mixer controls are read and changed through `amixer`, where `--sink` and
+// `--source` name the playback and capture controls
+
+const PLAYBACK: &str = "Master";
+const CAPTURE: &str = "Capture";
+
+pub fn is_available() -> bool {
+    Command::new("amixer")
+        .arg("info")
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+impl VolumeSpec {
+    pub(super) fn run_alsa(&self, modifier: VolumeCommand) -> Result<(), Error> {
+        let playback = match self.sink.as_str() {
+            DEFAULT_SINK => PLAYBACK,
+            control => control,
+        };
+        let capture = match self.source.as_str() {
+            DEFAULT_SOURCE => CAPTURE,
+            control => control,
+        };
+
+        let limits = self.limits(Some(playback));
+        let limits = VolumeLimits {
+            max: limits.max.min(100),
+            ..limits
+        };
+
+        match modifier {
+            VolumeCommand::Add { n } => {
+                let (volume, muted) = get_volume(playback)?;
+                let volume = volume.change(n as i32, limits);
+                set_volume(playback, volume)?;
+                self.feedback_output(playback, volume, muted, false)?;
+            }
+            VolumeCommand::Sub { n } => {
+                let (volume, muted) = get_volume(playback)?;
+                let volume = volume.change(-(n as i32), limits);
+                set_volume(playback, volume)?;
+                self.feedback_output(playback, volume, muted, false)?;
+            }
+            VolumeCommand::Set { n } => {
+                let (_, muted) = get_volume(playback)?;
+                let volume = DashiVolume::from(n).clamp(limits);
+                set_volume(playback, volume)?;
+                self.feedback_output(playback, volume, muted, false)?;
+            }
+            VolumeCommand::Get => {
+                let (volume, muted) = get_volume(playback)?;
+                self.feedback_output(playback, volume, muted, false)?;
+            }
+            VolumeCommand::Muted => {
+                let (volume, muted) = get_volume(playback)?;
+                self.feedback_output(playback, volume, muted, true)?;
+            }
+            VolumeCommand::MutedMic => {
+                let (volume, muted) = get_volume(capture)?;
+                self.feedback_input(capture, volume, muted)?;
+            }
+            VolumeCommand::Mute => {
+                let (volume, muted) = get_volume(playback)?;
+                amixer(&["set", playback, if muted { "unmute" } else { "mute" }])?;
+                self.feedback_output(playback, volume, !muted, true)?;
+            }
+            VolumeCommand::MuteMic => {
+                let (volume, muted) = get_volume(capture)?;
+                amixer(&["set", capture, if muted { "cap" } else { "nocap" }])?;
+                self.feedback_input(capture, volume, !muted)?;
+            }
+            VolumeCommand::Ptt { modifier } => {
+                let (volume, _) = get_volume(capture)?;
+                let muted = matches!(modifier, PttCommand::Release);
+                amixer(&["set", capture, if muted { "nocap" } else { "cap" }])?;
+                self.feedback_input(capture, volume, muted)?;
+            }
+            VolumeCommand::Mic { modifier } => {
+                let (mut volume, muted) = get_volume(capture)?;
+                match modifier {
+                    MicCommand::Add { n } => volume = volume.change(n as i32, limits),
+                    MicCommand::Sub { n } => volume = volume.change(-(n as i32), limits),
+                    MicCommand::Set { n } => volume = DashiVolume::from(n).clamp(limits),
+                    MicCommand::Get => {}
+                }
+
+                if !matches!(modifier, MicCommand::Get) {
+                    set_volume(capture, volume)?;
+                }
+
+                self.feedback_input_volume(capture, volume, muted)?;
+            }
+            _ => {
+                return Err(Error::msg(
+                    "This command is not supported by the ALSA backend, which requires a sound server",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn amixer(args: &[&str]) -> Result<String, Error> {
+    let output = Command::new("amixer").arg("-M").args(args).output()?;
+
+    if !output.status.success() {
+        return Err(Error::msg(format!(
+            "amixer {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Parses the first channel, e.g. `Front Left: Playback 45 [70%] [-12.00dB] [on]`,
+/// where `[off]` means muted or not capturing
+fn get_volume(control: &str) -> Result<(DashiVolume, bool), Error> {
+    let output = amixer(&["get", control])?;
+    let channel = output
+        .lines()
+        .find(|line| line.contains("%]"))
+        .ok_or(Error::msg(format!("Could not read volume of {}", control)))?;
+
+    let volume = channel
+        .split('[')
+        .find_map(|field| field.strip_suffix("%]")?.parse::<i32>().ok())
+        .ok_or(Error::msg(format!("Could not read volume of {}", control)))?;
+
+    Ok((DashiVolume(volume), channel.contains("[off]")))
+}
+
+fn set_volume(control: &str, volume: DashiVolume) -> Result<(), Error> {
+    amixer(&["set", control, &format!("{}%", volume)]).map(|_| ())
+}
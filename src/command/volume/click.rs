@@ -0,0 +1,257 @@
+use std::{fs, path::Path};
+
+use color_eyre::{eyre::Error, Result};
+use pulse::{
+    context::Context,
+    error::{Code, PAErr},
+    mainloop::standard::{IterateResult, Mainloop},
+    sample::{Format as SampleFormat, Spec},
+    stream::{SeekMode, State as StreamState, Stream},
+};
+
+use super::{PaFeedback, VolumeSpec};
+
+/// Sample of the freedesktop sound theme, available once another application uploaded
+/// it to the sample cache
+const THEME_SAMPLE: &str = "audio-volume-change";
+/// Name the configured WAV is uploaded as
+const CONFIGURED_SAMPLE: &str = "dashi-volume-change";
+
+impl VolumeSpec {
+    /// Plays the configured WAV, or the cached theme sample without one, as a click on
+    /// the sink. Failures are only reported, since the volume has already changed
+    pub(super) fn click(&self, main_loop: &mut Mainloop, context: &mut Context, sink: &str) {
+        if self.silent || !self.config.click {
+            return;
+        }
+
+        let Some(path) = &self.config.click_sample else {
+            // Dashi cannot decode the theme's Ogg file, so an uncached sample stays silent
+            let _ = self.play_sample(main_loop, context, THEME_SAMPLE, sink);
+            return;
+        };
+
+        if let Err(err) = self.play_click(main_loop, context, sink, path) {
+            eprintln!("Could not play the volume click: {}", err);
+        }
+    }
+
+    /// Plays the click through the sample cache, uploading it the first time it is missing
+    fn play_click(
+        &self,
+        main_loop: &mut Mainloop,
+        context: &mut Context,
+        sink: &str,
+        path: &Path,
+    ) -> Result<(), Error> {
+        if self
+            .play_sample(main_loop, context, CONFIGURED_SAMPLE, sink)
+            .is_err()
+        {
+            self.upload_sample(main_loop, context, CONFIGURED_SAMPLE, path)?;
+            self.play_sample(main_loop, context, CONFIGURED_SAMPLE, sink)?;
+        }
+
+        Ok(())
+    }
+
+    fn play_sample(
+        &self,
+        main_loop: &mut Mainloop,
+        context: &mut Context,
+        name: &str,
+        sink: &str,
+    ) -> Result<(), PAErr> {
+        let cmd_play_sample = PaFeedback::<()>::new();
+        cmd_play_sample.run(main_loop, |result| {
+            context.play_sample(
+                name,
+                Some(sink),
+                None,
+                Some(Box::new(move |success| {
+                    let mut result = result.lock().unwrap();
+                    match success {
+                        true => *result = Some(Ok(())),
+                        false => *result = Some(Err(PAErr::from(Code::NoEntity))),
+                    }
+                })),
+            );
+        })
+    }
+
+    fn upload_sample(
+        &self,
+        main_loop: &mut Mainloop,
+        context: &mut Context,
+        name: &str,
+        path: &Path,
+    ) -> Result<(), Error> {
+        let (spec, data) = read_wav(path)?;
+        let mut stream =
+            Stream::new(context, name, &spec, None).ok_or(PAErr::from(Code::Invalid))?;
+
+        stream.connect_upload(data.len())?;
+        wait_for(main_loop, &stream, StreamState::Ready)?;
+        stream.write(&data, None, 0, SeekMode::Relative)?;
+        stream.finish_upload()?;
+        wait_for(main_loop, &stream, StreamState::Terminated)
+    }
+}
+
+fn wait_for(main_loop: &mut Mainloop, stream: &Stream, state: StreamState) -> Result<(), Error> {
+    loop {
+        match stream.get_state() {
+            current if current == state => return Ok(()),
+            StreamState::Failed | StreamState::Terminated => {
+                return Err(Error::new(PAErr::from(Code::Internal)));
+            }
+            _ => {}
+        }
+
+        match main_loop.iterate(true) {
+            IterateResult::Success(_) => {}
+            IterateResult::Quit(_) => return Err(Error::new(PAErr::from(Code::Killed))),
+            IterateResult::Err(err) => return Err(Error::new(err)),
+        };
+    }
+}
+
+/// Reads the sample format and samples of an uncompressed WAV file
+fn read_wav(path: &Path) -> Result<(Spec, Vec<u8>), Error> {
+    parse_wav(&fs::read(path)?)
+        .ok_or_else(|| Error::msg(format!("{} is not a PCM WAV file", path.display())))
+}
+
+fn parse_wav(bytes: &[u8]) -> Option<(Spec, Vec<u8>)> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return None;
+    }
+
+    let mut spec = None;
+    let mut offset = 12;
+
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let size = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into().ok()?) as usize;
+        let chunk = bytes.get(offset + 8..offset + 8 + size)?;
+
+        match id {
+            b"fmt " if chunk.len() >= 16 => {
+                let tag = u16::from_le_bytes([chunk[0], chunk[1]]);
+                let channels = u16::from_le_bytes([chunk[2], chunk[3]]);
+                let rate = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+                let bits = u16::from_le_bytes([chunk[14], chunk[15]]);
+
+                let format = match (tag, bits) {
+                    (1, 8) => SampleFormat::U8,
+                    (1, 16) => SampleFormat::S16le,
+                    (1, 24) => SampleFormat::S24le,
+                    (1, 32) => SampleFormat::S32le,
+                    (3, 32) => SampleFormat::F32le,
+                    _ => return None,
+                };
+
+                spec = Some(Spec {
+                    format,
+                    rate,
+                    channels: channels as u8,
+                });
+            }
+            b"data" => {
+                let spec = spec.filter(|spec| spec.is_valid())?;
+                let frames = chunk.len() / spec.frame_size() * spec.frame_size();
+                return Some((spec, chunk[..frames].to_vec()));
+            }
+            _ => {}
+        }
+
+        // Chunks are padded to an even size
+        offset += 8 + size + size % 2;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = [id, &(data.len() as u32).to_le_bytes(), data].concat();
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn fmt(tag: u16, channels: u16, rate: u32, bits: u16) -> Vec<u8> {
+        let align = channels * bits / 8;
+        let data = [
+            &tag.to_le_bytes()[..],
+            &channels.to_le_bytes(),
+            &rate.to_le_bytes(),
+            &(rate * align as u32).to_le_bytes(),
+            &align.to_le_bytes(),
+            &bits.to_le_bytes(),
+        ]
+        .concat();
+        chunk(b"fmt ", &data)
+    }
+
+    fn wav(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body = [b"WAVE".to_vec(), chunks.concat()].concat();
+        [
+            b"RIFF".to_vec(),
+            (body.len() as u32).to_le_bytes().to_vec(),
+            body,
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn reads_pcm() {
+        let bytes = wav(&[
+            fmt(1, 2, 44100, 16),
+            chunk(b"data", &[1, 2, 3, 4, 5, 6, 7, 8]),
+        ]);
+        let (spec, data) = parse_wav(&bytes).unwrap();
+
+        assert!(spec.format == SampleFormat::S16le);
+        assert_eq!((spec.rate, spec.channels), (44100, 2));
+        assert_eq!(data, [1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn skips_padded_chunks() {
+        let bytes = wav(&[
+            fmt(1, 1, 8000, 8),
+            chunk(b"LIST", &[1, 2, 3]),
+            chunk(b"data", &[9, 8, 7]),
+        ]);
+        let (spec, data) = parse_wav(&bytes).unwrap();
+
+        assert!(spec.format == SampleFormat::U8);
+        assert_eq!(data, [9, 8, 7]);
+    }
+
+    #[test]
+    fn drops_partial_frames() {
+        let bytes = wav(&[fmt(1, 2, 44100, 16), chunk(b"data", &[1, 2, 3, 4, 5])]);
+        assert_eq!(parse_wav(&bytes).unwrap().1, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        // Compressed formats such as IMA ADPCM
+        let adpcm = wav(&[fmt(0x11, 1, 8000, 4), chunk(b"data", &[0; 4])]);
+        assert!(parse_wav(&adpcm).is_none());
+
+        let missing_fmt = wav(&[chunk(b"data", &[0; 4])]);
+        assert!(parse_wav(&missing_fmt).is_none());
+
+        let truncated = wav(&[fmt(1, 1, 8000, 16), chunk(b"data", &[0; 8])]);
+        assert!(parse_wav(&truncated[..truncated.len() - 2]).is_none());
+
+        assert!(parse_wav(b"OggS\0\x02\0\0\0\0\0\0\0\0").is_none());
+    }
+}
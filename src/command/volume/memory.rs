@@ -0,0 +1,38 @@
+use std::collections::HashMap;
+
+use color_eyre::eyre::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::io::{load_json, save_json};
+
+/// Last volume and mute of every sink, keyed by sink name
+#[derive(Deserialize, Serialize, Default)]
+pub struct Memory {
+    /// Default sink when the memory was last applied
+    pub default: String,
+    sinks: HashMap<String, Level>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub struct Level {
+    pub volume: i32,
+    pub muted: bool,
+}
+
+impl Memory {
+    pub fn new() -> Result<Memory, Error> {
+        load_json("volume.json")
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        save_json("volume.json", self)
+    }
+
+    pub fn remember(&mut self, sink: &str, volume: i32, muted: bool) {
+        self.sinks.insert(sink.to_string(), Level { volume, muted });
+    }
+
+    pub fn level(&self, sink: &str) -> Option<Level> {
+        self.sinks.get(sink).copied()
+    }
+}
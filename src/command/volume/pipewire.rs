@@ -0,0 +1,498 @@
+use std::process::{Command, Stdio};
+
+use color_eyre::{eyre::Error, Result};
+use pulse::channelmap::{Map as ChannelMap, Position};
+use pulse::volume::{ChannelVolumes, Volume as PaVolume, VolumeLinear};
+use serde_json::{Deserializer, Value};
+
+use crate::command::{AppCommand, MicCommand, PttCommand, VolumeCommand};
+
+use super::{
+    sync_led, DashiVolume, Memory, PaApp, PaDevice, PaEntry, VolumeSpec, DEFAULT_SINK, MUTE_LED,
+};
+
+// Nodes, streams and defaults are read from `pw-dump`. Channel volumes are written through
+// `pw-cli` like pipewire-pulse does, mutes and defaults through `wpctl`
+
+/// Audio devices and streams of the PipeWire graph
+struct Graph {
+    objects: Vec<Value>,
+    sinks: Vec<PaEntry>,
+    sources: Vec<PaEntry>,
+    apps: Vec<PaApp>,
+    default_sink: String,
+    default_source: String,
+}
+
+pub fn is_available() -> bool {
+    Command::new("wpctl")
+        .arg("status")
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+impl VolumeSpec {
+    pub(super) fn run_pipewire(&self, modifier: VolumeCommand) -> Result<(), Error> {
+        let mut graph = Graph::new()?;
+
+        match self.restore_pipewire(&graph) {
+            Ok(true) => graph = Graph::new()?,
+            Ok(false) => {}
+            Err(err) => eprintln!("Could not restore the sink volume: {}", err),
+        }
+
+        match modifier {
+            VolumeCommand::Add { n } => {
+                let mut output = graph.output(&self.sink)?;
+                output.volume = output
+                    .volume
+                    .change(n as i32, self.limits(Some(&output.name)));
+                graph.set_volume(&mut output)?;
+                self.remember(&output);
+                self.feedback_output(&output.name, output.volume, output.muted, false)?;
+            }
+            VolumeCommand::Sub { n } => {
+                let mut output = graph.output(&self.sink)?;
+                output.volume = output
+                    .volume
+                    .change(-(n as i32), self.limits(Some(&output.name)));
+                graph.set_volume(&mut output)?;
+                self.remember(&output);
+                self.feedback_output(&output.name, output.volume, output.muted, false)?;
+            }
+            VolumeCommand::Set { n } => {
+                let mut output = graph.output(&self.sink)?;
+                output.volume = DashiVolume::from(n).clamp(self.limits(Some(&output.name)));
+                graph.set_volume(&mut output)?;
+                self.remember(&output);
+                self.feedback_output(&output.name, output.volume, output.muted, false)?;
+            }
+            VolumeCommand::Get => {
+                let output = graph.output(&self.sink)?;
+                self.feedback_output(&output.name, output.volume, output.muted, false)?;
+            }
+            VolumeCommand::Muted => {
+                let output = graph.output(&self.sink)?;
+                self.feedback_output(&output.name, output.volume, output.muted, true)?;
+            }
+            VolumeCommand::MutedMic => {
+                let input = graph.input(&self.source)?;
+                self.feedback_input(&input.name, input.volume, input.muted)?;
+            }
+            VolumeCommand::Mute => {
+                let mut output = graph.output(&self.sink)?;
+                output.muted = !output.muted;
+                set_mute(output.index, output.muted)?;
+                self.remember(&output);
+                self.feedback_output(&output.name, output.volume, output.muted, true)?;
+            }
+            VolumeCommand::MuteMic => {
+                let input = graph.input(&self.source)?;
+                set_mute(input.index, !input.muted)?;
+                self.feedback_input(&input.name, input.volume, !input.muted)?;
+            }
+            VolumeCommand::Ptt { modifier } => {
+                let input = graph.input(&self.source)?;
+                let muted = matches!(modifier, PttCommand::Release);
+                set_mute(input.index, muted)?;
+                self.feedback_input(&input.name, input.volume, muted)?;
+            }
+            VolumeCommand::Mic { modifier } => {
+                let mut input = graph.input(&self.source)?;
+                let limits = self.limits(None);
+                match modifier {
+                    MicCommand::Add { n } => input.volume = input.volume.change(n as i32, limits),
+                    MicCommand::Sub { n } => {
+                        input.volume = input.volume.change(-(n as i32), limits)
+                    }
+                    MicCommand::Set { n } => input.volume = DashiVolume::from(n).clamp(limits),
+                    MicCommand::Get => {}
+                }
+
+                if !matches!(modifier, MicCommand::Get) {
+                    graph.set_volume(&mut input)?;
+                }
+
+                self.feedback_input_volume(&input.name, input.volume, input.muted)?;
+            }
+            VolumeCommand::Sinks => self.list(&graph.sinks, &graph.default_sink),
+            VolumeCommand::Sources => self.list(&graph.sources, &graph.default_source),
+            VolumeCommand::Default { sink } => {
+                let sink = Self::find_sink(&graph.sinks, &sink)?;
+                set_default(sink)?;
+                self.switched_pipewire();
+                self.feedback_sink(sink)?;
+            }
+            VolumeCommand::NextSink => {
+                let sink = self.next_sink(&graph.sinks, &graph.default_sink)?;
+                set_default(sink)?;
+                self.switched_pipewire();
+                self.feedback_sink(sink)?;
+            }
+            VolumeCommand::Apps => self.list_apps(&graph.apps),
+            VolumeCommand::App { target, modifier } => {
+                let apps = std::mem::take(&mut graph.apps);
+                self.change_apps(apps, &target, &modifier, |app| match modifier {
+                    AppCommand::Mute => set_mute(app.index, app.muted),
+                    _ => {
+                        app.volume.set(&mut app.channels);
+                        graph.set_channels(app.index, &app.channels)
+                    }
+                })?;
+            }
+            VolumeCommand::Balance { n } => {
+                let mut output = graph.output(&self.sink)?;
+                if let Some(n) = n {
+                    output.set_balance(n)?;
+                    graph.set_channels(output.index, &output.channels)?;
+                }
+
+                self.feedback_balance(&output)?;
+            }
+            VolumeCommand::Watch => self.watch_pipewire()?,
+            VolumeCommand::Profiles { .. }
+            | VolumeCommand::Profile { .. }
+            | VolumeCommand::ToggleHeadset
+            | VolumeCommand::Daemon => {
+                return Err(Error::msg(
+                    "This command is not supported by the PipeWire backend. Try: --backend pulse",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reapplies the remembered volume and mute once a sink becomes the default, and
+    /// returns whether they were applied
+    fn restore_pipewire(&self, graph: &Graph) -> Result<bool, Error> {
+        let mut memory = Memory::new()?;
+
+        if memory.default == graph.default_sink {
+            return Ok(false);
+        }
+
+        let level = memory.level(&graph.default_sink);
+        if let Some(level) = level {
+            let mut output = graph.output(DEFAULT_SINK)?;
+            output.volume = DashiVolume(level.volume);
+            output.muted = level.muted;
+
+            graph.set_volume(&mut output)?;
+            set_mute(output.index, output.muted)?;
+            sync_led(MUTE_LED, output.muted);
+        }
+
+        memory.default = graph.default_sink.clone();
+        memory.save()?;
+        Ok(level.is_some())
+    }
+
+    /// Restores the new default sink after switching
+    fn switched_pipewire(&self) {
+        if let Err(err) = Graph::new().and_then(|graph| self.restore_pipewire(&graph)) {
+            eprintln!("Could not restore the sink volume: {}", err);
+        }
+    }
+
+    /// Prints the sink state whenever `pw-dump` reports a change of the graph
+    fn watch_pipewire(&self) -> Result<(), Error> {
+        let mut monitor = Command::new("pw-dump")
+            .arg("--monitor")
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdout = monitor
+            .stdout
+            .take()
+            .ok_or(Error::msg("Could not monitor PipeWire"))?;
+
+        let mut last = None;
+
+        for update in Deserializer::from_reader(stdout).into_iter::<Value>() {
+            update?;
+
+            let graph = Graph::new()?;
+            if let Ok(output) = graph.output(&self.sink) {
+                self.watch_output(&output, &mut last);
+            }
+
+            if let Ok(input) = graph.input(&self.source) {
+                self.sync_input_led(input.muted);
+            }
+        }
+
+        Err(Error::msg("Lost connection to PipeWire"))
+    }
+}
+
+impl Graph {
+    fn new() -> Result<Graph, Error> {
+        let output = Command::new("pw-dump").output()?;
+        let objects = serde_json::from_slice::<Vec<Value>>(&output.stdout)?;
+
+        let mut graph = Graph {
+            objects: Vec::new(),
+            sinks: Vec::new(),
+            sources: Vec::new(),
+            apps: Vec::new(),
+            default_sink: String::new(),
+            default_source: String::new(),
+        };
+
+        for object in &objects {
+            match object["type"].as_str() {
+                Some("PipeWire:Interface:Node") => {
+                    let props = &object["info"]["props"];
+                    let index = object["id"].as_u64().unwrap_or_default() as u32;
+                    let entry = PaEntry {
+                        index,
+                        name: props["node.name"].as_str().unwrap_or_default().to_string(),
+                        description: props["node.description"]
+                            .as_str()
+                            .unwrap_or_default()
+                            .to_string(),
+                        state: object["info"]["state"]
+                            .as_str()
+                            .unwrap_or_default()
+                            .to_string(),
+                        available: true,
+                    };
+
+                    match props["media.class"].as_str() {
+                        Some("Audio/Sink") => graph.sinks.push(entry),
+                        Some("Audio/Source") => graph.sources.push(entry),
+                        Some("Stream/Output/Audio") => {
+                            if let Some((channels, _, muted)) = read_channels(object) {
+                                graph.apps.push(PaApp {
+                                    index,
+                                    name: props["application.name"]
+                                        .as_str()
+                                        .unwrap_or(&entry.name)
+                                        .to_string(),
+                                    binary: props["application.process.binary"]
+                                        .as_str()
+                                        .unwrap_or_default()
+                                        .to_string(),
+                                    pid: number(&props["application.process.id"])
+                                        .map(|pid| pid as u32),
+                                    channels,
+                                    volume: channels.max().into(),
+                                    muted,
+                                });
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Some("PipeWire:Interface:Metadata")
+                    if object["props"]["metadata.name"] == "default" =>
+                {
+                    for entry in object["metadata"].as_array().into_iter().flatten() {
+                        let name = entry["value"]["name"].as_str().unwrap_or_default();
+                        match entry["key"].as_str() {
+                            Some("default.audio.sink") => graph.default_sink = name.to_string(),
+                            Some("default.audio.source") => graph.default_source = name.to_string(),
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        graph.objects = objects;
+        Ok(graph)
+    }
+
+    /// Sink selected by `--sink`
+    fn output(&self, selected: &str) -> Result<PaDevice, Error> {
+        self.device(&self.sinks, selected, &self.default_sink)
+    }
+
+    /// Source selected by `--source`
+    fn input(&self, selected: &str) -> Result<PaDevice, Error> {
+        self.device(&self.sources, selected, &self.default_source)
+    }
+
+    /// Device selected through its name or id, where `@DEFAULT_SINK@` and
+    /// `@DEFAULT_SOURCE@` select the default
+    fn device(
+        &self,
+        entries: &[PaEntry],
+        selected: &str,
+        default: &str,
+    ) -> Result<PaDevice, Error> {
+        let selected = if selected.starts_with("@DEFAULT_") {
+            default
+        } else {
+            selected
+        };
+
+        let entry = entries
+            .iter()
+            .find(|entry| entry.name == selected || entry.index.to_string() == selected)
+            .ok_or(Error::msg(format!("Could not find device {}", selected)))?;
+        let (channels, channel_map, muted) = read_channels(self.object(entry.index)?).ok_or(
+            Error::msg(format!("Could not read volume of {}", entry.name)),
+        )?;
+
+        Ok(PaDevice {
+            index: entry.index,
+            name: entry.name.clone(),
+            channels,
+            channel_map,
+            volume: channels.max().into(),
+            muted,
+        })
+    }
+
+    fn object(&self, id: u32) -> Result<&Value, Error> {
+        self.objects
+            .iter()
+            .find(|object| object["id"].as_u64() == Some(id as u64))
+            .ok_or(Error::msg(format!("Could not find PipeWire object {}", id)))
+    }
+
+    /// Scales every channel so the loudest matches the volume, which keeps the balance
+    fn set_volume(&self, device: &mut PaDevice) -> Result<(), Error> {
+        device.volume.set(&mut device.channels);
+        self.set_channels(device.index, &device.channels)
+    }
+
+    /// Nodes of a sound card are changed through the route of their card profile,
+    /// which changes the hardware volume, while streams are changed directly
+    fn set_channels(&self, id: u32, channels: &ChannelVolumes) -> Result<(), Error> {
+        let volumes = channels
+            .get()
+            .iter()
+            .map(|volume| format!("{:.6}", VolumeLinear::from(*volume).0))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let props = &self.object(id)?["info"]["props"];
+
+        if let (Some(device), Some(profile_device)) = (
+            number(&props["device.id"]),
+            number(&props["card.profile.device"]),
+        ) && let Some(route) = self.route(device, profile_device)
+        {
+            let route = format!(
+                "{{ index: {}, device: {}, props: {{ channelVolumes: [ {} ] }}, save: true }}",
+                route, profile_device, volumes
+            );
+            return run(
+                "pw-cli",
+                &["set-param", &device.to_string(), "Route", &route],
+            );
+        }
+
+        let props = format!("{{ channelVolumes: [ {} ] }}", volumes);
+        run("pw-cli", &["set-param", &id.to_string(), "Props", &props])
+    }
+
+    /// Index of the active route of a card profile device
+    fn route(&self, device: u64, profile_device: u64) -> Option<u64> {
+        self.object(device as u32).ok()?["info"]["params"]["Route"]
+            .as_array()?
+            .iter()
+            .find(|route| number(&route["device"]) == Some(profile_device))
+            .and_then(|route| number(&route["index"]))
+    }
+}
+
+/// Channel volumes, positions and mute from the `Props` of a node
+fn read_channels(node: &Value) -> Option<(ChannelVolumes, ChannelMap, bool)> {
+    let props = node["info"]["params"]["Props"]
+        .as_array()?
+        .iter()
+        .find(|props| props["channelVolumes"].is_array())?;
+    let volumes = props["channelVolumes"].as_array()?;
+    let positions: Vec<&str> = match props["channelMap"].as_array() {
+        Some(positions) => positions.iter().filter_map(Value::as_str).collect(),
+        None => node["info"]["props"]["audio.position"]
+            .as_str()
+            .map(|positions| positions.split(',').map(str::trim).collect())
+            .unwrap_or_default(),
+    };
+
+    let len = volumes.len().min(ChannelVolumes::CHANNELS_MAX as usize);
+    if len == 0 {
+        return None;
+    }
+
+    let mut channels = ChannelVolumes::default();
+    let mut channel_map = ChannelMap::default();
+    channels.set_len(len as u8);
+    channel_map.set_len(len as u8);
+
+    for (i, volume) in volumes.iter().take(len).enumerate() {
+        channels.get_mut()[i] = PaVolume::from(VolumeLinear(volume.as_f64().unwrap_or_default()));
+        channel_map.get_mut()[i] = positions
+            .get(i)
+            .map_or(Position::Mono, |name| position(name));
+    }
+
+    Some((
+        channels,
+        channel_map,
+        props["mute"].as_bool().unwrap_or_default(),
+    ))
+}
+
+/// PulseAudio position of a PipeWire channel name
+fn position(name: &str) -> Position {
+    match name {
+        "FL" => Position::FrontLeft,
+        "FR" => Position::FrontRight,
+        "FC" => Position::FrontCenter,
+        "LFE" => Position::Lfe,
+        "SL" => Position::SideLeft,
+        "SR" => Position::SideRight,
+        "FLC" => Position::FrontLeftOfCenter,
+        "FRC" => Position::FrontRightOfCenter,
+        "RC" => Position::RearCenter,
+        "RL" => Position::RearLeft,
+        "RR" => Position::RearRight,
+        "TC" => Position::TopCenter,
+        "TFL" => Position::TopFrontLeft,
+        "TFC" => Position::TopFrontCenter,
+        "TFR" => Position::TopFrontRight,
+        "TRL" => Position::TopRearLeft,
+        "TRC" => Position::TopRearCenter,
+        "TRR" => Position::TopRearRight,
+        _ => Position::Mono,
+    }
+}
+
+/// Ids are numbers in `pw-dump`, but some properties are strings
+fn number(value: &Value) -> Option<u64> {
+    value
+        .as_u64()
+        .or_else(|| value.as_str().and_then(|value| value.parse().ok()))
+}
+
+fn run(program: &str, args: &[&str]) -> Result<(), Error> {
+    let output = Command::new(program).args(args).output()?;
+
+    if !output.status.success() {
+        return Err(Error::msg(format!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(())
+}
+
+fn set_mute(id: u32, muted: bool) -> Result<(), Error> {
+    run(
+        "wpctl",
+        &["set-mute", &id.to_string(), if muted { "1" } else { "0" }],
+    )
+}
+
+/// Streams following the default are moved by the session manager
+fn set_default(sink: &PaEntry) -> Result<(), Error> {
+    run("wpctl", &["set-default", &sink.index.to_string()])
+}
@@ -0,0 +1,179 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use serde::Deserialize;
+
+use crate::io::config_dir_file;
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct Config {
+    pub brightness: BrightnessConfig,
+    pub backlight: BacklightConfig,
+    pub volume: VolumeConfig,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct BrightnessConfig {
+    /// Fade duration in milliseconds
+    pub fade: u64,
+    pub curve: Curve,
+    /// Exponent of the exponential curve
+    pub gamma: f32,
+    /// Granularity in percent that brightness is rounded to
+    pub step: u32,
+    pub auto: AutoBrightnessConfig,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct AutoBrightnessConfig {
+    /// Brightness in percent in complete darkness
+    pub min: f32,
+    /// Illuminance in lux mapped to full brightness
+    pub max_lux: f32,
+    /// Sensor polling interval in milliseconds
+    pub interval: u64,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct BacklightConfig {
+    /// Fade duration in milliseconds
+    pub fade: u64,
+    pub idle: IdleConfig,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct IdleConfig {
+    /// Seconds without input before the backlight turns off
+    pub timeout: u64,
+    /// Keeps the backlight on while plugged in
+    pub never_on_ac: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct VolumeConfig {
+    /// Sink names that `volume next-sink` cycles through, every sink when empty
+    pub cycle: Vec<String>,
+    /// Sink name prefixes the volume daemon prefers, highest priority first
+    pub priority: Vec<String>,
+    /// Keeps the default source muted by the volume daemon while nothing is recording
+    pub auto_mute_mic: bool,
+    /// Plays a click after changing the volume
+    pub click: bool,
+    /// PCM WAV file played as the click instead of the sound theme's `audio-volume-change`
+    pub click_sample: Option<PathBuf>,
+    /// Highest volume in percent
+    pub max: u32,
+    /// Granularity in percent that `add` and `sub` round to
+    pub step: u32,
+    /// Allows volume above 100%, otherwise `max` is capped at 100
+    pub overamplify: bool,
+    /// Overrides of the above by sink name
+    pub sinks: HashMap<String, SinkConfig>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct SinkConfig {
+    pub max: Option<u32>,
+    pub step: Option<u32>,
+    pub overamplify: Option<bool>,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Curve {
+    #[default]
+    Linear,
+    Exponential,
+}
+
+impl Config {
+    /// Falls back to defaults when the config is missing or invalid, so commands that
+    /// do not read it keep working
+    pub fn load() -> Config {
+        let Ok(string) = config_dir_file("config.json").and_then(fs::read_to_string) else {
+            return Config::default();
+        };
+
+        serde_json::from_str::<Config>(&string).unwrap_or_else(|err| {
+            eprintln!("Invalid dashi config, using defaults: {}", err);
+            Config::default()
+        })
+    }
+}
+
+impl VolumeConfig {
+    /// Volume ceiling of the sink, or the global one without a sink
+    pub fn max(&self, sink: Option<&str>) -> u32 {
+        let sink = sink.and_then(|sink| self.sinks.get(sink));
+        let max = sink.and_then(|sink| sink.max).unwrap_or(self.max);
+        let overamplify = sink
+            .and_then(|sink| sink.overamplify)
+            .unwrap_or(self.overamplify);
+
+        if overamplify {
+            max
+        } else {
+            max.min(100)
+        }
+    }
+
+    pub fn step(&self, sink: Option<&str>) -> u32 {
+        sink.and_then(|sink| self.sinks.get(sink))
+            .and_then(|sink| sink.step)
+            .unwrap_or(self.step)
+            .max(1)
+    }
+}
+
+impl Default for BrightnessConfig {
+    fn default() -> Self {
+        Self {
+            fade: 0,
+            curve: Curve::default(),
+            gamma: 2.2,
+            step: 5,
+            auto: AutoBrightnessConfig::default(),
+        }
+    }
+}
+
+impl Default for AutoBrightnessConfig {
+    fn default() -> Self {
+        Self {
+            min: 5.0,
+            max_lux: 1000.0,
+            interval: 1000,
+        }
+    }
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        Self {
+            timeout: 30,
+            never_on_ac: false,
+        }
+    }
+}
+
+impl Default for VolumeConfig {
+    fn default() -> Self {
+        Self {
+            cycle: Vec::new(),
+            priority: Vec::new(),
+            auto_mute_mic: false,
+            click: false,
+            click_sample: None,
+            max: 150,
+            step: 5,
+            overamplify: true,
+            sinks: HashMap::new(),
+        }
+    }
+}
@@ -1,9 +1,10 @@
-use std::os::fd::{IntoRawFd, RawFd};
+use std::os::fd::{AsRawFd, BorrowedFd, IntoRawFd, RawFd};
 use std::process::Command;
 
 use color_eyre::eyre::Error;
 use color_eyre::Result;
-use nix::sys::socket::{self, AddressFamily, SockFlag, SockType, UnixAddr};
+use nix::sys::socket::{self, AddressFamily, Backlog, SockFlag, SockType, UnixAddr};
+use nix::unistd;
 
 use nix::errno::Errno as NixErrno;
 
@@ -26,7 +27,7 @@ impl Daemon {
         let socket = socket::socket(
             AddressFamily::Unix,
             SockType::Stream,
-            SockFlag::empty(),
+            SockFlag::SOCK_NONBLOCK,
             None,
         )?
         .into_raw_fd();
@@ -41,4 +42,32 @@ impl Daemon {
             Err(generic) => Err(Error::new(generic)),
         }
     }
+
+    /// Accepts interruptions from other processes once the socket is bound by this daemon
+    pub fn listen(&self) -> Result<()> {
+        // The socket is owned by this daemon and only closed through `close`
+        let socket = unsafe { BorrowedFd::borrow_raw(self.socket) };
+        Ok(socket::listen(&socket, Backlog::new(1)?)?)
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        socket::accept(self.socket)
+            .map(|peer| unistd::close(peer).ok())
+            .is_ok()
+    }
+
+    pub fn interrupt(&self) -> Result<()> {
+        let socket = socket::socket(
+            AddressFamily::Unix,
+            SockType::Stream,
+            SockFlag::empty(),
+            None,
+        )?;
+
+        Ok(socket::connect(socket.as_raw_fd(), &self.address)?)
+    }
+
+    pub fn close(self) -> Result<()> {
+        Ok(unistd::close(self.socket)?)
+    }
 }
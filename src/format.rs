@@ -0,0 +1,20 @@
+use std::fmt::Display;
+
+use clap::ValueEnum;
+use serde_json::Value;
+
+#[derive(ValueEnum, Clone, Copy, Default)]
+pub enum Format {
+    #[default]
+    Plain,
+    Json,
+}
+
+impl Format {
+    pub fn print(&self, plain: impl Display, json: Value) {
+        match self {
+            Format::Plain => println!("{}", plain),
+            Format::Json => println!("{}", json),
+        }
+    }
+}
@@ -1,4 +1,12 @@
-use std::{fs, io, path::PathBuf};
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    process,
+};
+
+use color_eyre::eyre::Error;
+use serde::{de::DeserializeOwned, Serialize};
 
 pub fn data_dir_file(added: &str) -> Result<PathBuf, io::Error> {
     data_dir().map(|dir| dir.join(added))
@@ -15,3 +23,48 @@ pub fn data_dir() -> Result<PathBuf, io::Error> {
 
     dir
 }
+
+/// State saved by `save_json` in the data dir, or its default if nothing was saved yet
+/// or the file is invalid
+pub fn load_json<T: DeserializeOwned + Default>(name: &str) -> Result<T, Error> {
+    let Ok(string) = fs::read_to_string(data_dir_file(name)?) else {
+        return Ok(T::default());
+    };
+
+    Ok(serde_json::from_str::<T>(&string).unwrap_or_else(|err| {
+        eprintln!("Invalid {}, starting over: {}", name, err);
+        T::default()
+    }))
+}
+
+/// Writes a temporary file and renames it over the old one, so concurrent commands
+/// never read a partially written file
+pub fn save_json<T: Serialize>(name: &str, value: &T) -> Result<(), Error> {
+    let path = data_dir_file(name)?;
+    let temporary = path.with_extension(format!("{}.tmp", process::id()));
+
+    let mut writer = BufWriter::new(File::create(&temporary)?);
+    serde_json::to_writer_pretty(&mut writer, value)?;
+    writer.flush()?;
+    drop(writer);
+
+    Ok(fs::rename(temporary, path)?)
+}
+
+/// Files that only matter while the session runs, falling back to the data dir
+pub fn runtime_dir_file(added: &str) -> Result<PathBuf, io::Error> {
+    match dirs::runtime_dir() {
+        Some(dir) => {
+            let dir = dir.join("dashi/");
+            fs::create_dir_all(&dir)?;
+            Ok(dir.join(added))
+        }
+        None => data_dir_file(added),
+    }
+}
+
+pub fn config_dir_file(added: &str) -> Result<PathBuf, io::Error> {
+    dirs::config_dir()
+        .map(|dir| dir.join("dashi/").join(added))
+        .ok_or(io::Error::from(io::ErrorKind::NotFound))
+}
@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+
+use color_eyre::eyre::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    command::power::is_plugged,
+    io::{load_json, save_json},
+    sysfs::{Backend, Device},
+};
+
+/// Raw brightness per device, keyed by `subsystem/name`
+#[derive(Deserialize, Serialize, Default)]
+pub struct Levels {
+    saved: HashMap<String, u32>,
+    ac: HashMap<String, u32>,
+    battery: HashMap<String, u32>,
+}
+
+impl Levels {
+    pub fn new() -> Result<Levels, Error> {
+        load_json("levels.json")
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        save_json("levels.json", self)
+    }
+
+    pub fn store(&mut self, device: &Device) -> Result<(), Error> {
+        self.saved.insert(device.id(), device.brightness()?);
+        Ok(())
+    }
+
+    pub fn stored(&self, device: &Device) -> Option<u32> {
+        self.saved.get(&device.id()).copied()
+    }
+
+    pub fn remember(&mut self, device: &Device, plugged: bool) -> Result<(), Error> {
+        let levels = if plugged {
+            &mut self.ac
+        } else {
+            &mut self.battery
+        };
+
+        levels.insert(device.id(), device.brightness()?);
+        Ok(())
+    }
+
+    /// Remembers the new level of the devices for the current power source, restored by
+    /// the power daemon. Failures are only reported, since the level has already changed
+    pub fn remember_current(devices: &[Device]) {
+        let Ok(plugged) = is_plugged() else {
+            return;
+        };
+
+        let result = Levels::new().and_then(|mut levels| {
+            for device in devices {
+                levels.remember(device, plugged)?;
+            }
+            levels.save()
+        });
+
+        if let Err(err) = result {
+            eprintln!("Could not remember the brightness level: {}", err);
+        }
+    }
+
+    /// Restores every device to the level last used on the given power source
+    pub fn apply(&self, plugged: bool) -> Result<(), Error> {
+        let levels = if plugged { &self.ac } else { &self.battery };
+        let backend = Backend::new();
+
+        for (id, raw) in levels {
+            if let Some(device) = Device::from_id(id) {
+                backend.set_brightness(&device, *raw)?;
+            }
+        }
+
+        Ok(())
+    }
+}
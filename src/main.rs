@@ -8,17 +8,25 @@ use crate::command::{
     brightness::BrightnessSpec, nightshift::NightShiftSpec, power::PowerSpec, volume::VolumeSpec,
     Command,
 };
+use crate::config::Config;
+use crate::format::Format;
 
 mod command;
+mod config;
 mod daemon;
+mod format;
 mod io;
+mod levels;
 mod notify;
+mod sysfs;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Args {
     #[arg(short, long)]
     silent: bool,
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
     #[command(subcommand)]
     command: Command,
 }
@@ -27,15 +35,53 @@ fn main() -> Result<()> {
     color_eyre::install()?;
 
     let args = Args::parse();
+    let config = Config::load();
 
     let result = match args.command {
-        Command::Brightness { modifier } => BrightnessSpec::new(args.silent).run(modifier),
-        Command::Backlight { modifier } => BacklightSpec::new(args.silent).run(modifier),
-        Command::Volume { modifier } => VolumeSpec::new(args.silent).run(modifier),
+        Command::Brightness {
+            device,
+            all,
+            fade,
+            modifier,
+        } => BrightnessSpec::new(
+            args.silent,
+            args.format,
+            device,
+            all,
+            fade.unwrap_or(config.brightness.fade),
+            config.brightness,
+        )
+        .run(modifier),
+        Command::Backlight {
+            device,
+            fade,
+            modifier,
+        } => BacklightSpec::new(
+            args.silent,
+            args.format,
+            device,
+            fade.unwrap_or(config.backlight.fade),
+            config.backlight,
+        )
+        .run(modifier),
+        Command::Volume {
+            sink,
+            source,
+            backend,
+            modifier,
+        } => VolumeSpec::new(
+            args.silent,
+            args.format,
+            sink,
+            source,
+            backend,
+            config.volume,
+        )
+        .run(modifier),
         Command::Bookmark { modifier } => BookmarkSpec::new(args.silent)?.run(modifier),
-        Command::Bluetooth { modifier } => BluetoothSpec::new(args.silent).run(modifier),
-        Command::Power { modifier } => PowerSpec::new(args.silent).run(modifier),
-        Command::Nightshift { modifier } => NightShiftSpec::new(args.silent).run(modifier),
+        Command::Bluetooth { modifier } => BluetoothSpec::new(args.silent, args.format).run(modifier),
+        Command::Power { modifier } => PowerSpec::new(args.silent, args.format).run(modifier),
+        Command::Nightshift { modifier } => NightShiftSpec::new(args.silent, args.format).run(modifier),
     };
 
     match result {
@@ -0,0 +1,317 @@
+use std::{
+    fmt::Display,
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom},
+    os::fd::AsFd,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use color_eyre::{eyre::Error, Result};
+use nix::{
+    poll::{poll, PollFd, PollFlags},
+    time::{clock_gettime, ClockId},
+};
+use zbus::blocking::{Connection, Proxy};
+
+use crate::{daemon::Daemon, io::runtime_dir_file};
+
+pub struct Device {
+    subsystem: String,
+    name: String,
+    path: PathBuf,
+}
+
+// Dbus Docs: https://www.freedesktop.org/software/systemd/man/latest/org.freedesktop.login1.html
+
+pub enum Backend {
+    Logind(Proxy<'static>),
+    Sysfs,
+}
+
+pub struct Fade {
+    daemon: Option<Daemon>,
+    duration: Duration,
+}
+
+const FADE_FRAME: Duration = Duration::from_millis(16);
+const WATCH_POLL: Duration = Duration::from_millis(200);
+const FADE_TAKEOVER_ATTEMPTS: u32 = 30;
+
+impl Device {
+    pub fn list(subsystem: &str) -> Result<Vec<Device>, Error> {
+        Self::list_in(subsystem, &Path::new("/sys/class/").join(subsystem))
+    }
+
+    pub fn list_in(subsystem: &str, directory: &Path) -> Result<Vec<Device>, Error> {
+        let mut devices = directory
+            .read_dir()
+            .map_err(|_| Error::msg(format!("Could not find devices in {}", directory.display())))?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                entry.file_name().to_str().map(|name| Device {
+                    subsystem: subsystem.to_string(),
+                    name: name.to_string(),
+                    path: entry.path(),
+                })
+            })
+            .collect::<Vec<Device>>();
+
+        devices.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(devices)
+    }
+
+    /// Resolves an id created by `Device::id` if the device is still present
+    pub fn from_id(id: &str) -> Option<Device> {
+        let (subsystem, name) = id.split_once('/')?;
+        let path = Path::new("/sys/class/").join(subsystem).join(name);
+
+        path.exists().then(|| Device {
+            subsystem: subsystem.to_string(),
+            name: name.to_string(),
+            path,
+        })
+    }
+
+    pub fn id(&self) -> String {
+        format!("{}/{}", self.subsystem, self.name)
+    }
+
+    pub fn subsystem(&self) -> &str {
+        &self.subsystem
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn read(&self, attribute: &str) -> Result<String, Error> {
+        let as_string = fs::read_to_string(self.path.join(attribute))?;
+        Ok(as_string.trim_end_matches(['\n', '\0']).to_string())
+    }
+
+    pub fn read_u32(&self, attribute: &str) -> Result<u32, Error> {
+        Ok(self.read(attribute)?.parse::<u32>()?)
+    }
+
+    pub fn read_f32(&self, attribute: &str) -> Result<f32, Error> {
+        Ok(self.read(attribute)?.parse::<f32>()?)
+    }
+
+    pub fn write(&self, attribute: &str, value: impl Display) -> Result<(), Error> {
+        Ok(fs::write(
+            self.path.join(attribute),
+            format!("{}\0", value),
+        )?)
+    }
+
+    pub fn brightness(&self) -> Result<u32, Error> {
+        self.read_u32("brightness")
+    }
+
+    pub fn max_brightness(&self) -> Result<u32, Error> {
+        self.read_u32("max_brightness")
+    }
+
+    /// Calls `changed` whenever the brightness of a device is changed outside of dashi.
+    /// Firmware changes are reported by the kernel through `brightness_hw_changed` where
+    /// available, other changes are polled and skipped right after dashi wrote the device
+    pub fn watch(devices: &[Device], mut changed: impl FnMut(&Device) -> Result<()>) -> Result<()> {
+        let read = |device: &Device| {
+            device
+                .read("actual_brightness")
+                .or_else(|_| device.read("brightness"))
+                .ok()
+        };
+
+        // Reading fails with ENODATA until the first firmware change, but still arms `poll`
+        let mut hw_changed = devices
+            .iter()
+            .enumerate()
+            .filter_map(|(i, device)| {
+                let mut file = File::open(device.path.join("brightness_hw_changed")).ok()?;
+                let _ = file.read_to_string(&mut String::new());
+                Some((i, file))
+            })
+            .collect::<Vec<_>>();
+        let mut values = devices.iter().map(read).collect::<Vec<_>>();
+
+        loop {
+            let mut fds = hw_changed
+                .iter()
+                .map(|(_, file)| PollFd::new(file.as_fd(), PollFlags::POLLPRI))
+                .collect::<Vec<_>>();
+            poll(&mut fds, WATCH_POLL.as_millis() as u16)?;
+
+            let ready = fds
+                .iter()
+                .map(|fd| {
+                    fd.revents().is_some_and(|events| {
+                        events.intersects(PollFlags::POLLPRI | PollFlags::POLLERR)
+                    })
+                })
+                .collect::<Vec<_>>();
+            drop(fds);
+
+            for ((i, file), ready) in hw_changed.iter_mut().zip(ready) {
+                if ready {
+                    file.seek(SeekFrom::Start(0))?;
+                    let _ = file.read_to_string(&mut String::new());
+                    values[*i] = read(&devices[*i]);
+                    changed(&devices[*i])?;
+                }
+            }
+
+            for (device, value) in devices.iter().zip(values.iter_mut()) {
+                let current = read(device);
+                if current == *value {
+                    continue;
+                }
+
+                *value = current;
+                if !device.written_within(WATCH_POLL * 2) {
+                    changed(device)?;
+                }
+            }
+        }
+    }
+
+    /// Marks the device as written by dashi, which `Device::watch` ignores
+    fn mark_written(&self) {
+        if let Ok(marker) = self.marker() {
+            let _ = fs::write(marker, "");
+        }
+    }
+
+    fn written_within(&self, duration: Duration) -> bool {
+        self.marker()
+            .and_then(fs::metadata)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|elapsed| elapsed <= duration)
+    }
+
+    fn marker(&self) -> Result<PathBuf, std::io::Error> {
+        runtime_dir_file(&format!("written-{}-{}", self.subsystem, self.name))
+    }
+}
+
+impl Backend {
+    /// Brightness is written through the logind session when available,
+    /// which works for session users without any udev rules installed
+    pub fn new() -> Backend {
+        Connection::system()
+            .and_then(|connection| {
+                Proxy::new(
+                    &connection,
+                    "org.freedesktop.login1",
+                    "/org/freedesktop/login1/session/auto",
+                    "org.freedesktop.login1.Session",
+                )
+            })
+            .map(Backend::Logind)
+            .unwrap_or(Backend::Sysfs)
+    }
+
+    pub fn set_brightness(&self, device: &Device, value: u32) -> Result<(), Error> {
+        device.mark_written();
+
+        if let Backend::Logind(proxy) = self
+            && proxy
+                .call_method("SetBrightness", &(device.subsystem(), device.name(), value))
+                .is_ok()
+        {
+            return Ok(());
+        }
+
+        device.write("brightness", value)
+    }
+
+    /// Time since the session became idle, which requires the compositor or an idle
+    /// daemon to set the logind idle hint (e.g. `swayidle idlehint <seconds>`)
+    pub fn idle_time(&self) -> Result<Duration, Error> {
+        let Backend::Logind(proxy) = self else {
+            return Err(Error::msg("Idle state requires a systemd-logind session"));
+        };
+
+        if !proxy.get_property::<bool>("IdleHint")? {
+            return Ok(Duration::ZERO);
+        }
+
+        let since = Duration::from_micros(proxy.get_property::<u64>("IdleSinceHintMonotonic")?);
+        let now = Duration::from(clock_gettime(ClockId::CLOCK_MONOTONIC)?);
+
+        Ok(now.saturating_sub(since))
+    }
+}
+
+impl Fade {
+    /// Interrupts any in-flight fade of the subsystem, which jumps straight to its
+    /// target, so the brightness read afterwards is where the previous key press ended
+    pub fn acquire(subsystem: &str, duration: Duration) -> Result<Fade, Error> {
+        if duration.is_zero() {
+            return Ok(Fade {
+                daemon: None,
+                duration,
+            });
+        }
+
+        let daemon = Daemon::new(&format!("dashi-fade-{}", subsystem))?;
+
+        for _ in 0..FADE_TAKEOVER_ATTEMPTS {
+            if !daemon.is_running()? {
+                daemon.listen()?;
+                return Ok(Fade {
+                    daemon: Some(daemon),
+                    duration,
+                });
+            }
+
+            let _ = daemon.interrupt();
+            thread::sleep(FADE_FRAME);
+        }
+
+        Ok(Fade {
+            daemon: None,
+            duration: Duration::ZERO,
+        })
+    }
+
+    pub fn run(self, backend: &Backend, targets: &[(&Device, u32)]) -> Result<(), Error> {
+        if let Some(daemon) = self.daemon {
+            let starts = targets
+                .iter()
+                .map(|(device, _)| device.brightness())
+                .collect::<Result<Vec<u32>, Error>>()?;
+            let steps = (self.duration.as_millis() / FADE_FRAME.as_millis()).max(1) as u32;
+
+            for step in 1..steps {
+                if daemon.is_interrupted() {
+                    break;
+                }
+
+                for ((device, target), start) in targets.iter().zip(&starts) {
+                    let progress = step as f32 / steps as f32;
+                    let value = *start as f32 + (*target as f32 - *start as f32) * progress;
+                    backend.set_brightness(device, value.round() as u32)?;
+                }
+
+                thread::sleep(FADE_FRAME);
+            }
+
+            for (device, target) in targets {
+                backend.set_brightness(device, *target)?;
+            }
+
+            daemon.close()
+        } else {
+            for (device, target) in targets {
+                backend.set_brightness(device, *target)?;
+            }
+
+            Ok(())
+        }
+    }
+}